@@ -0,0 +1,33 @@
+use lexopt_cli::prelude::*;
+
+#[derive(Parser, Debug)]
+#[cli(
+    name = "es",
+    about = "Just another command to manage the command line arguments",
+    version = "0.0.1",
+    author = "Vincenzo Palazzo <vincenzopalazzo@member.fsf.org>"
+)]
+pub struct CliArgs {
+    #[subcommand]
+    pub command: Command,
+    /// verbose flag
+    #[arg(short = 'v')]
+    pub verbose: bool,
+}
+
+#[derive(SubCommand, Debug)]
+pub enum Command {
+    /// Install a new package
+    Install {
+        /// The name of the package
+        #[arg(short)]
+        name: String,
+    },
+    /// Say hello
+    Hello { name: String },
+}
+
+fn main() {
+    let args = CliArgs::parse();
+    println!("{:?}", args);
+}
@@ -0,0 +1,201 @@
+//! Declarative command line parsing on top of lexopt.
+//!
+//! This crate is the only dependency needed to use the derive
+//! macros, it re-exports the macros, the runtime used by the
+//! generated code and lexopt itself.
+//!
+//! ```no_run
+//! use lexopt_cli::prelude::*;
+//!
+//! #[derive(Parser, Debug)]
+//! #[cli(name = "es", version = "0.0.1")]
+//! pub struct CliArgs {
+//!     #[arg(short = 'v')]
+//!     pub verbose: bool,
+//! }
+//!
+//! fn main() {
+//!     let args = CliArgs::parse();
+//!     println!("{args:?}");
+//! }
+//! ```
+//!
+//! A misuse of the macros is reported with a compile error that
+//! points to the wrong token, like an unknown `arg` attribute:
+//!
+//! ```compile_fail
+//! use lexopt_cli::prelude::*;
+//!
+//! #[derive(Parser)]
+//! pub struct CliArgs {
+//!     #[arg(shrt = 'v')]
+//!     pub verbose: bool,
+//! }
+//! ```
+//!
+//! or a `SubCommand` derived on a struct:
+//!
+//! ```compile_fail
+//! use lexopt_cli::prelude::*;
+//!
+//! #[derive(SubCommand)]
+//! pub struct Install {
+//!     name: String,
+//! }
+//! ```
+//!
+//! or positional indices that leave a gap:
+//!
+//! ```compile_fail
+//! use lexopt_cli::prelude::*;
+//!
+//! #[derive(Parser)]
+//! #[cli(name = "es")]
+//! pub struct CliArgs {
+//!     #[arg(index = 1)]
+//!     pub from: String,
+//!     #[arg(index = 3)]
+//!     pub to: String,
+//! }
+//! ```
+//!
+//! or `bool_value` on an argument that is not a switch:
+//!
+//! ```compile_fail
+//! use lexopt_cli::prelude::*;
+//!
+//! #[derive(Parser)]
+//! #[cli(name = "es")]
+//! pub struct CliArgs {
+//!     #[arg(bool_value)]
+//!     pub name: String,
+//! }
+//! ```
+//!
+//! or an optional list of values:
+//!
+//! ```compile_fail
+//! use lexopt_cli::prelude::*;
+//!
+//! #[derive(Parser)]
+//! #[cli(name = "es")]
+//! pub struct CliArgs {
+//!     pub tag: Option<Vec<String>>,
+//! }
+//! ```
+//!
+//! or a `Parser` derived on an enum:
+//!
+//! ```compile_fail
+//! use lexopt_cli::prelude::*;
+//!
+//! #[derive(Parser)]
+//! pub enum Command {
+//!     Install { name: String },
+//! }
+//! ```
+//!
+//! or a `cli` value that is not a string literal:
+//!
+//! ```compile_fail
+//! use lexopt_cli::prelude::*;
+//!
+//! #[derive(Parser)]
+//! #[cli(name = "es", version = 1)]
+//! pub struct CliArgs {
+//!     pub verbose: bool,
+//! }
+//! ```
+//!
+//! or two arguments with the same short name:
+//!
+//! ```compile_fail
+//! use lexopt_cli::prelude::*;
+//!
+//! #[derive(Parser)]
+//! #[cli(name = "es")]
+//! pub struct CliArgs {
+//!     #[arg(short)]
+//!     pub verbose: bool,
+//!     #[arg(short)]
+//!     pub version: bool,
+//! }
+//! ```
+//!
+//! or a counted argument that is not an integer:
+//!
+//! ```compile_fail
+//! use lexopt_cli::prelude::*;
+//!
+//! #[derive(Parser)]
+//! #[cli(name = "es")]
+//! pub struct CliArgs {
+//!     #[arg(count)]
+//!     pub verbose: Vec<String>,
+//! }
+//! ```
+//!
+//! or a `ValueEnum` with a variant that is not a unit:
+//!
+//! ```compile_fail
+//! use lexopt_cli::prelude::*;
+//!
+//! #[derive(ValueEnum)]
+//! pub enum Format {
+//!     Json,
+//!     Custom(String),
+//! }
+//! ```
+//!
+//! or a `#[flatten]` field with an option that is already used:
+//!
+//! ```compile_fail
+//! use lexopt_cli::prelude::*;
+//!
+//! #[derive(Parser)]
+//! pub struct Common {
+//!     pub verbose: bool,
+//! }
+//!
+//! #[derive(Parser)]
+//! #[cli(name = "es")]
+//! pub struct CliArgs {
+//!     #[flatten]
+//!     pub common: Common,
+//!     pub verbose: bool,
+//! }
+//! ```
+//!
+//! The builtin `-h/--help` and `-V/--version` leave their names to the
+//! options of the flattened parsers, but one of them must stay free:
+//!
+//! ```compile_fail
+//! use lexopt_cli::prelude::*;
+//!
+//! #[derive(Parser)]
+//! pub struct Conn {
+//!     #[arg(short)]
+//!     pub host: Option<String>,
+//! }
+//!
+//! #[derive(Parser)]
+//! #[cli(name = "es")]
+//! pub struct CliArgs {
+//!     #[flatten]
+//!     pub conn: Conn,
+//!     pub help: bool,
+//! }
+//! ```
+pub use lexopt;
+pub use lexopt_derive::{cli, help, Parser, SubCommand, ValueEnum};
+pub use lexopt_helper::prelude::{
+    ArgNames, DisplayArg, DisplayCommand, Error, Parser, ParserInfo, RenameAll, SubCommand,
+    ValueEnum,
+};
+
+/// All the items needed to define and run a parser, the generated
+/// code refers to this module with an absolute path.
+pub mod prelude {
+    pub use lexopt_derive::{cli, help, Parser, SubCommand, ValueEnum};
+    pub use lexopt_helper::prelude::*;
+}
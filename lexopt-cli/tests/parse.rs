@@ -0,0 +1,1251 @@
+//! The parsers are checked with `try_parse_from`, while the trace and
+//! the output of `parse` are printed by the process, so those cases
+//! run this binary again and check what it prints.
+use std::process::Command as Process;
+
+use lexopt_cli::prelude::*;
+
+#[derive(Parser, Debug)]
+#[cli(name = "switches", about = "Switches")]
+pub struct Switches {
+    pub verbose: bool,
+    pub dry_run: bool,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "install", about = "Subcommands")]
+pub struct WithCommand {
+    #[subcommand]
+    pub command: Command,
+    pub verbose: bool,
+}
+
+#[derive(SubCommand, Debug)]
+pub enum Command {
+    Install {
+        force: bool,
+    },
+    Add {
+        #[arg(short = 'n', long = "pkg-name")]
+        name: String,
+    },
+    InstallPkg {
+        dry_run: bool,
+    },
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "names", about = "Names")]
+pub struct Names {
+    #[arg(short = 'v', long = "verbose-mode")]
+    pub verbose: bool,
+    #[arg(short = 'n')]
+    pub name: String,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "snake", about = "Snake case", rename_all = "snake_case")]
+pub struct SnakeCase {
+    #[subcommand]
+    pub command: Command,
+    pub dry_run: bool,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "verbatim", about = "Verbatim", rename_all = "verbatim")]
+pub struct Verbatim {
+    #[subcommand]
+    pub command: Command,
+    pub dry_run: bool,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "types", about = "Types")]
+pub struct Types {
+    pub name: Option<String>,
+    pub tag: Vec<String>,
+    pub jobs: u32,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "positionals", about = "Positionals")]
+pub struct Positionals {
+    #[arg(positional)]
+    pub to: String,
+    #[arg(index = 1)]
+    pub from: String,
+    #[arg(positional)]
+    pub rest: Vec<String>,
+    pub force: bool,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "pair", about = "Pair")]
+pub struct Pair {
+    #[arg(positional)]
+    pub from: String,
+    #[arg(positional)]
+    pub to: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "versioned", version = "1.2.3", author = "Jane Doe")]
+pub struct Versioned {
+    pub verbose: bool,
+}
+
+/// A documented command.
+///
+/// It has also a long description.
+#[derive(Parser, Debug)]
+#[cli(name = "documented")]
+pub struct Documented {
+    #[subcommand]
+    pub command: Documentation,
+    /// Do not touch anything.
+    ///
+    /// Really, nothing at all.
+    pub dry_run: bool,
+}
+
+#[derive(SubCommand, Debug)]
+pub enum Documentation {
+    /// Build the project
+    Build {
+        /// The "release" profile
+        release: bool,
+    },
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "tool")]
+pub struct Tool {
+    #[subcommand]
+    pub command: ToolCommand,
+}
+
+#[derive(SubCommand, Debug)]
+pub enum ToolCommand {
+    /// Build the project
+    Build(BuildArgs),
+    Check(BuildArgs),
+}
+
+/// The arguments shared by the subcommands
+#[derive(Parser, Debug)]
+pub struct BuildArgs {
+    #[arg(short = 'r')]
+    pub release: bool,
+    pub jobs: Option<u32>,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "git")]
+pub struct Git {
+    #[subcommand]
+    pub command: Option<GitCommand>,
+}
+
+#[derive(SubCommand, Debug)]
+pub enum GitCommand {
+    /// Manage the remotes
+    Remote {
+        #[arg(short = 'q')]
+        quiet: bool,
+        #[subcommand]
+        action: RemoteAction,
+    },
+    Stash(StashArgs),
+}
+
+#[derive(SubCommand, Debug)]
+pub enum RemoteAction {
+    /// Add a remote
+    Add {
+        #[arg(positional)]
+        name: String,
+        #[arg(positional)]
+        url: String,
+    },
+    Remove {
+        #[arg(positional)]
+        name: String,
+    },
+}
+
+#[derive(Parser, Debug)]
+pub struct StashArgs {
+    #[subcommand]
+    pub action: Option<StashAction>,
+}
+
+#[derive(SubCommand, Debug)]
+pub enum StashAction {
+    Pop {
+        #[arg(positional)]
+        index: Option<u32>,
+    },
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "shorts")]
+pub struct Shorts {
+    #[arg(short)]
+    pub all: bool,
+    #[arg(short)]
+    pub long_listing: bool,
+    #[arg(short)]
+    pub name: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "counts")]
+pub struct Counts {
+    #[arg(short, count)]
+    pub verbose: u8,
+    #[arg(short)]
+    pub quiet: bool,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "env", env_prefix = "PRB_")]
+pub struct Env {
+    #[arg(env)]
+    pub level: Option<u32>,
+    #[arg(env)]
+    pub jobs: u32,
+    #[arg(env)]
+    pub tag: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "plain-env")]
+pub struct PlainEnv {
+    #[arg(env = "PLAIN_HOST")]
+    pub host: String,
+}
+
+/// The host used when `--host` is missing.
+fn localhost() -> String {
+    "localhost".to_owned()
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "defaults")]
+pub struct Defaults {
+    #[arg(default = "8080")]
+    pub port: u16,
+    #[arg(default_expr = localhost())]
+    pub host: String,
+    #[arg(env = "DEFAULTS_LEVEL", default = "1")]
+    pub level: u32,
+}
+
+#[derive(ValueEnum, Debug)]
+#[value(ignore_case)]
+pub enum Format {
+    Json,
+    #[value(alias = "yml")]
+    Yaml,
+    #[value(name = "tsv")]
+    TabSeparated,
+}
+
+#[derive(ValueEnum, Debug)]
+pub enum Level {
+    Low,
+    VeryHigh,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "output")]
+pub struct Output {
+    #[arg(value_enum)]
+    pub format: Format,
+    #[arg(value_enum)]
+    pub level: Option<Level>,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "flatten")]
+pub struct Outer {
+    #[flatten]
+    pub common: Common,
+    #[arg(positional)]
+    pub target: String,
+}
+
+/// The options shared by the commands
+#[derive(Parser, Debug)]
+pub struct Common {
+    #[arg(short, count)]
+    pub verbose: u8,
+    #[arg(default = "text")]
+    pub format: String,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "deploy")]
+pub struct Deploy {
+    #[subcommand]
+    pub command: DeployCommand,
+}
+
+#[derive(SubCommand, Debug)]
+pub enum DeployCommand {
+    Run {
+        #[flatten]
+        common: Common,
+        #[arg(short)]
+        force: bool,
+    },
+}
+
+/// The default number of jobs, named like the field that uses it.
+fn jobs() -> u32 {
+    4
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "jobs")]
+pub struct Jobs {
+    #[arg(default_expr = jobs())]
+    pub jobs: u32,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "escaped")]
+pub struct Escaped {
+    #[arg(long = "dir\\name")]
+    pub dir: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "paths")]
+pub struct Paths {
+    pub name: std::option::Option<String>,
+    pub tag: std::vec::Vec<String>,
+}
+
+#[cli(name = "above", version = "0.1.0", rename_all = "snake_case")]
+#[derive(Parser, Debug)]
+pub struct Above {
+    pub dry_run: bool,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "inherent")]
+pub struct Inherent {
+    #[subcommand]
+    pub command: InherentCommand,
+    pub verbose: bool,
+}
+
+/// The user types can have their own inherent items, like the
+/// ones that the derives generated before.
+impl Inherent {
+    pub fn new(verbose: bool) -> Self {
+        Inherent {
+            command: InherentCommand::parse_install(),
+            verbose,
+        }
+    }
+}
+
+#[derive(SubCommand, Debug)]
+pub enum InherentCommand {
+    Install { force: bool },
+}
+
+impl InherentCommand {
+    pub fn parse_install() -> Self {
+        InherentCommand::Install { force: true }
+    }
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "locals")]
+pub struct Locals {
+    pub parser: Option<String>,
+    pub trace: bool,
+    pub arg: Vec<String>,
+    #[arg(default = "1")]
+    pub value: u32,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "colors")]
+pub struct Colors {
+    #[arg(short, bool_value)]
+    pub color: bool,
+    #[arg(bool_value)]
+    pub pager: Option<bool>,
+}
+
+/// A command with an empty `#[cli]`.
+#[derive(Parser, Debug)]
+#[cli]
+pub struct EmptyCli {
+    pub verbose: bool,
+}
+
+/// The same command with `#[cli]` above the derive.
+#[cli]
+#[derive(Parser, Debug)]
+pub struct EmptyCliAbove {
+    pub verbose: bool,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "shadowing")]
+pub struct Shadowing {
+    pub verbose: bool,
+}
+
+/// The inherent items with the names of the trait items are not
+/// called by the generated code.
+impl Shadowing {
+    pub fn display_args(verbose: bool) -> &'static str {
+        match verbose {
+            true => "verbose",
+            false => "quiet",
+        }
+    }
+
+    pub fn flags(&self) -> usize {
+        self.verbose as usize
+    }
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "prefixed", env_prefix = "PRB_")]
+pub struct Prefixed {
+    #[arg(env)]
+    pub user: Option<String>,
+    #[arg(env = "LEXOPT_TEST_HOST")]
+    pub host: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "env-switch", env_prefix = "PRB_")]
+pub struct EnvSwitch {
+    #[arg(env)]
+    pub sw: bool,
+}
+
+/// The default host, named like the closure of the `host` field.
+fn default_host() -> String {
+    "localhost".to_owned()
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "server")]
+pub struct Server {
+    #[arg(default_expr = Self::default_port())]
+    pub port: u16,
+    #[arg(default_expr = default_host())]
+    pub host: String,
+    #[subcommand]
+    pub command: Option<ServerCommand>,
+}
+
+impl Server {
+    fn default_port() -> u16 {
+        8080
+    }
+}
+
+#[derive(SubCommand, Debug)]
+pub enum ServerCommand {
+    Start {
+        #[arg(default_expr = Self::default_workers())]
+        workers: u32,
+    },
+}
+
+impl ServerCommand {
+    fn default_workers() -> u32 {
+        4
+    }
+}
+
+/// The connection options, `-h` and `-V` are not the builtin ones.
+#[derive(Parser, Debug)]
+pub struct Conn {
+    #[arg(short)]
+    pub host: Option<String>,
+    #[arg(short = 'V')]
+    pub verify: bool,
+}
+
+#[derive(Parser, Debug)]
+#[cli(name = "remote", version = "0.1.0")]
+pub struct Remote {
+    #[flatten]
+    pub conn: Conn,
+    #[subcommand]
+    pub command: Option<RemoteCommand>,
+}
+
+#[derive(SubCommand, Debug)]
+pub enum RemoteCommand {
+    Ping {
+        #[flatten]
+        conn: Conn,
+    },
+}
+
+/// The generated code does not depend on the imports of the module,
+/// and it is not confused by the types with the same name.
+pub mod hygiene {
+    pub struct Error;
+    pub struct ParserInfo;
+
+    #[derive(lexopt_cli::Parser, Debug)]
+    pub struct Hygiene {
+        #[subcommand]
+        pub command: Action,
+        pub verbose: bool,
+    }
+
+    #[derive(lexopt_cli::SubCommand, Debug)]
+    pub enum Action {
+        Run { name: Option<String> },
+    }
+}
+
+/// A parser written by hand, that reads the first value as a number.
+#[derive(Debug)]
+pub struct Mock(pub u32);
+
+impl Parser for Mock {
+    fn display_command(_: RenameAll) -> DisplayCommand {
+        DisplayCommand {
+            name: "mock".to_owned(),
+            subcommands: vec![],
+            optional_subcommand: false,
+            args: vec![],
+            usage: String::new(),
+            description: String::new(),
+            long_description: String::new(),
+            version: String::new(),
+            author: String::new(),
+        }
+    }
+
+    fn parse_args(parser: &mut ParserInfo) -> Result<Self, Error> {
+        match parser.next()? {
+            Some(Value(value)) => Ok(Mock(value.parse()?)),
+            Some(arg) => Err(arg.unexpected().into()),
+            None => Err(Error::MissingArgument("<value>".to_owned())),
+        }
+    }
+}
+
+/// Parse the arguments of the process with any parser, `parse`
+/// exits the process on errors and for the help or the version.
+fn parse<P: Parser + std::fmt::Debug>() -> String {
+    format!("{:?}", P::parse())
+}
+
+/// Parse the arguments of the process with the parser of the case.
+fn parse_case(case: &str) -> String {
+    match case {
+        "switches" => parse::<Switches>(),
+        "versioned" => parse::<Versioned>(),
+        "remote" => parse::<Remote>(),
+        _ => panic!("unknown case `{case}`"),
+    }
+}
+
+/// Run the case in a new process with the given environment
+/// variables, and return what it prints on stdout and stderr.
+fn run(case: &str, args: &[&str], envs: &[(&str, &str)]) -> (String, String) {
+    let output = Process::new(std::env::current_exe().unwrap())
+        .args(args)
+        .env("LEXOPT_TEST_CASE", case)
+        .envs(envs.iter().copied())
+        .output()
+        .unwrap();
+    (
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+/// Parse the arguments with the parser and check the result.
+fn check<P: Parser + std::fmt::Debug>(args: &[&str], expected: &str) {
+    let result = P::try_parse_from(args);
+    assert_eq!(format!("{result:?}"), expected, "{args:?}");
+}
+
+/// Run the case in a new process and check that the output
+/// is the only thing printed on stdout.
+fn check_output(case: &str, args: &[&str], expected: &str) {
+    let (stdout, _) = run(case, args, &[]);
+    assert_eq!(
+        stdout,
+        format!("{expected}\n"),
+        "case `{case}` with {args:?}"
+    );
+}
+
+/// Parse the arguments with the parser and check that the
+/// help contains the expected text.
+fn check_help<P: Parser + std::fmt::Debug>(args: &[&str], expected: &str) {
+    match P::try_parse_from(args) {
+        Err(Error::DisplayHelp(help)) => {
+            assert!(help.contains(expected), "{args:?}:\n{help}")
+        }
+        other => panic!("{args:?}: expected the help, found {other:?}"),
+    }
+}
+
+/// Check the command tree registered by the derives.
+fn check_command_map() {
+    let mut parser = ParserInfo::new();
+    parser.register_command(WithCommand::command());
+    let mut keys: Vec<_> = parser.command_map.keys().cloned().collect();
+    keys.sort();
+    assert_eq!(keys, ["@", "add", "install", "install-pkg"]);
+
+    let command = &parser.command_map["@"];
+    assert_eq!(command.name, "install");
+    assert_eq!(command.usage, "install [OPTIONS] <COMMAND>");
+    let verbose = &command.args[0];
+    assert_eq!(verbose.long_name, "verbose");
+    assert!(verbose.optional && !verbose.takes_value);
+
+    let add = &parser.command_map["add"];
+    assert_eq!(add.usage, "install add [OPTIONS]");
+    let name = &add.args[0];
+    assert_eq!(
+        (name.long_name.as_str(), name.short_name.as_str()),
+        ("pkg-name", "n")
+    );
+    assert!(!name.optional && name.takes_value);
+
+    let mut parser = ParserInfo::new();
+    parser.register_command(Positionals::command());
+    let command = &parser.command_map["@"];
+    assert_eq!(command.usage, "positionals [OPTIONS] <from> <to> [rest]...");
+
+    let mut parser = ParserInfo::new();
+    parser.register_command(Git::command());
+    let mut keys: Vec<_> = parser.command_map.keys().cloned().collect();
+    keys.sort();
+    assert_eq!(
+        keys,
+        [
+            "@",
+            "remote",
+            "remote add",
+            "remote remove",
+            "stash",
+            "stash pop"
+        ]
+    );
+    // the optional subcommands are in brackets
+    assert_eq!(parser.command_map["@"].usage, "git [OPTIONS] [COMMAND]");
+    assert_eq!(
+        parser.command_map["stash"].usage,
+        "git stash [OPTIONS] [COMMAND]"
+    );
+    assert_eq!(
+        parser.command_map["remote"].usage,
+        "git remote [OPTIONS] <COMMAND>"
+    );
+}
+
+fn main() {
+    if let Ok(case) = std::env::var("LEXOPT_TEST_CASE") {
+        println!("{}", parse_case(&case));
+        return;
+    }
+
+    // switches are false when missing and true when present
+    check::<Switches>(&[], "Ok(Switches { verbose: false, dry_run: false })");
+    check::<Switches>(
+        &["--verbose"],
+        "Ok(Switches { verbose: true, dry_run: false })",
+    );
+    check::<Switches>(
+        &["--dry-run", "--verbose"],
+        "Ok(Switches { verbose: true, dry_run: true })",
+    );
+    check::<WithCommand>(
+        &["install", "--force"],
+        "Ok(WithCommand { command: Install { force: true }, verbose: false })",
+    );
+    check::<WithCommand>(
+        &["--verbose", "install"],
+        "Ok(WithCommand { command: Install { force: false }, verbose: true })",
+    );
+
+    // the names are the ones declared inside `#[arg(...)]`
+    check::<Names>(
+        &["-v", "-n", "foo"],
+        "Ok(Names { verbose: true, name: \"foo\" })",
+    );
+    check::<Names>(
+        &["--verbose-mode", "--name", "foo"],
+        "Ok(Names { verbose: true, name: \"foo\" })",
+    );
+    check::<Names>(&["--verbose"], "Err(Lexopt(invalid option '--verbose'))");
+    check::<WithCommand>(
+        &["add", "-n", "foo"],
+        "Ok(WithCommand { command: Add { name: \"foo\" }, verbose: false })",
+    );
+    check::<WithCommand>(
+        &["add", "--pkg-name", "foo"],
+        "Ok(WithCommand { command: Add { name: \"foo\" }, verbose: false })",
+    );
+
+    // the names are kebab-case by default, and `rename_all`
+    // changes them for all the command tree
+    check::<WithCommand>(
+        &["install-pkg", "--dry-run"],
+        "Ok(WithCommand { command: InstallPkg { dry_run: true }, verbose: false })",
+    );
+    check::<SnakeCase>(
+        &["--dry_run", "install_pkg", "--dry_run"],
+        "Ok(SnakeCase { command: InstallPkg { dry_run: true }, dry_run: true })",
+    );
+    check::<SnakeCase>(&["--dry-run"], "Err(Lexopt(invalid option '--dry-run'))");
+    check::<Verbatim>(
+        &["--dry_run", "InstallPkg", "--dry_run"],
+        "Ok(Verbatim { command: InstallPkg { dry_run: true }, dry_run: true })",
+    );
+
+    // `Option<T>` stays `None` when missing and `Vec<T>`
+    // collects all the occurrences
+    check::<Types>(
+        &["--jobs", "2"],
+        "Ok(Types { name: None, tag: [], jobs: 2 })",
+    );
+    check::<Types>(
+        &["--tag", "a", "--name", "foo", "--tag", "b", "--jobs", "2"],
+        "Ok(Types { name: Some(\"foo\"), tag: [\"a\", \"b\"], jobs: 2 })",
+    );
+    check::<Types>(
+        &["--jobs", "two"],
+        "Err(Lexopt(cannot parse argument \"two\": invalid digit found in string))",
+    );
+
+    // the required arguments and subcommands must be present
+    check::<Names>(&["-v"], "Err(MissingArgument(\"--name\"))");
+    check::<Types>(&[], "Err(MissingArgument(\"--jobs\"))");
+    check::<WithCommand>(&["--verbose"], "Err(MissingSubCommand(\"command\"))");
+    check::<WithCommand>(&["add"], "Err(MissingArgument(\"--pkg-name\"))");
+
+    // the positionals follow the index, or the order of the fields
+    check::<Positionals>(
+        &["a", "--force", "b", "c", "d"],
+        "Ok(Positionals { to: \"b\", from: \"a\", rest: [\"c\", \"d\"], force: true })",
+    );
+    check::<Positionals>(
+        &["a", "b", "--", "--force"],
+        "Ok(Positionals { to: \"b\", from: \"a\", rest: [\"--force\"], force: false })",
+    );
+    check::<Positionals>(&["a"], "Err(MissingArgument(\"<to>\"))");
+    check::<Pair>(&["a"], "Ok(Pair { from: \"a\", to: None })");
+    check::<Pair>(&["a", "b", "c"], "Err(Lexopt(unexpected argument \"c\"))");
+    check::<Verbatim>(
+        &["install-pkg"],
+        "Err(Lexopt(unexpected argument \"install-pkg\"))",
+    );
+
+    check_command_map();
+
+    // `-h/--help` prints the help of the command that is parsed
+    check_help::<WithCommand>(
+        &["--help"],
+        "Usage: install [OPTIONS] <COMMAND>
+
+Commands:
+  install
+  add
+  install-pkg
+
+Options:
+      --verbose
+  -h, --help     Print help
+",
+    );
+    check_help::<WithCommand>(
+        &["add", "-h"],
+        "Usage: install add [OPTIONS]
+
+Options:
+  -n, --pkg-name <pkg-name>
+  -h, --help                 Print help
+",
+    );
+    check_help::<Positionals>(
+        &["a", "-h"],
+        "Usage: positionals [OPTIONS] <from> <to> [rest]...
+
+Arguments:
+  <from>
+  <to>
+  [rest]...
+",
+    );
+
+    // the metadata of `#[cli]` is attached to the type and
+    // `-V/--version` prints the name and the version
+    assert_eq!(Versioned::NAME, "versioned");
+    assert_eq!(Versioned::VERSION, Some("1.2.3"));
+    assert_eq!(Versioned::AUTHOR, Some("Jane Doe"));
+    assert_eq!(Versioned::ABOUT, None);
+    check::<Versioned>(
+        &["--version"],
+        "Err(DisplayVersion(\"versioned 1.2.3\\n\"))",
+    );
+    check_output("versioned", &["-V"], "versioned 1.2.3");
+    let (stdout, _) = run("versioned", &["--help"], &[]);
+    assert!(
+        stdout.starts_with("versioned 1.2.3\nJane Doe\n"),
+        "{stdout}"
+    );
+    let (stdout, stderr) = run("versioned", &["--name"], &[]);
+    assert_eq!(stdout, "");
+    assert_eq!(stderr, "error: invalid option '--name'\n");
+    check_help::<Versioned>(
+        &["-h"],
+        "versioned 1.2.3
+Jane Doe
+Usage: versioned [OPTIONS]
+",
+    );
+    check_help::<Versioned>(&["-h"], "  -V, --version  Print version\n");
+    check::<Switches>(&["--version"], "Err(Lexopt(invalid option '--version'))");
+
+    // the doc comments are the descriptions, the first paragraph
+    // is the short help and all of them are the long help
+    check_help::<Documented>(
+        &["-h"],
+        "A documented command.
+
+Usage: documented [OPTIONS] <COMMAND>
+
+Commands:
+  build  Build the project
+
+Options:
+      --dry-run  Do not touch anything.
+",
+    );
+    check_help::<Documented>(
+        &["--help"],
+        "A documented command.
+
+It has also a long description.
+
+Usage: documented [OPTIONS] <COMMAND>
+",
+    );
+    check_help::<Documented>(
+        &["--help"],
+        "      --dry-run  Do not touch anything.
+
+                 Really, nothing at all.
+",
+    );
+    check_help::<Documented>(
+        &["build", "-h"],
+        "Build the project
+
+Usage: documented build [OPTIONS]
+
+Options:
+      --release  The \"release\" profile
+",
+    );
+
+    // the trace is printed on stderr only when it is enabled
+    let (stdout, stderr) = run("switches", &["--verbose"], &[("LEXOPT_TRACE", "1")]);
+    assert_eq!(stdout, "Switches { verbose: true, dry_run: false }\n");
+    assert!(
+        stderr.contains("lexopt: token Long(\"verbose\")\n"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("lexopt: matched `verbose`\n"), "{stderr}");
+    let (_, stderr) = run("switches", &["--verbose"], &[("LEXOPT_TRACE", "0")]);
+    assert_eq!(stderr, "");
+
+    // the tuple variants parse the arguments with the wrapped struct
+    check::<Tool>(
+        &["build", "-r", "--jobs", "2"],
+        "Ok(Tool { command: Build(BuildArgs { release: true, jobs: Some(2) }) })",
+    );
+    check::<Tool>(
+        &["check"],
+        "Ok(Tool { command: Check(BuildArgs { release: false, jobs: None }) })",
+    );
+    check_help::<Tool>(
+        &["build", "--help"],
+        "Build the project
+
+Usage: tool build [OPTIONS]
+
+Options:
+  -r, --release
+      --jobs <jobs>
+  -h, --help         Print help
+",
+    );
+
+    // the subcommands can be nested inside the variants and
+    // inside the wrapped structs
+    check::<Git>(&[], "Ok(Git { command: None })");
+    check::<Git>(&["remote", "-q", "add", "origin", "url"],
+        "Ok(Git { command: Some(Remote { quiet: true, action: Add { name: \"origin\", url: \"url\" } }) })",
+    );
+    check::<Git>(
+        &["remote", "remove", "origin"],
+        "Ok(Git { command: Some(Remote { quiet: false, action: Remove { name: \"origin\" } }) })",
+    );
+    check::<Git>(&["remote"], "Err(MissingSubCommand(\"action\"))");
+    check::<Git>(
+        &["stash"],
+        "Ok(Git { command: Some(Stash(StashArgs { action: None })) })",
+    );
+    check::<Git>(
+        &["stash", "pop", "1"],
+        "Ok(Git { command: Some(Stash(StashArgs { action: Some(Pop { index: Some(1) }) })) })",
+    );
+    check_help::<Git>(
+        &["remote", "add", "-h"],
+        "Add a remote
+
+Usage: git remote add [OPTIONS] <name> <url>
+",
+    );
+    check_help::<Git>(
+        &["remote", "-h"],
+        "Commands:
+  add     Add a remote
+  remove
+",
+    );
+
+    check::<hygiene::Hygiene>(
+        &["--verbose", "run", "--name", "foo"],
+        "Ok(Hygiene { command: Run { name: Some(\"foo\") }, verbose: true })",
+    );
+
+    // the derives implement the `Parser` trait, that can be
+    // implemented also by hand
+    check::<Mock>(&["42"], "Ok(Mock(42))");
+    check::<Mock>(&[], "Err(MissingArgument(\"<value>\"))");
+    assert_eq!(Mock::command().name, "mock");
+    assert_eq!(Git::command().subcommands.len(), 2);
+
+    // any iterator of arguments can be parsed, without the binary name
+    let args = vec![std::ffi::OsString::from("--verbose")];
+    let switches = Switches::try_parse_from(args).unwrap();
+    assert!(switches.verbose && !switches.dry_run);
+
+    // `#[arg(short)]` takes the first letter of the field, the short
+    // switches can be clustered and the short values attached
+    check::<Shorts>(
+        &["-al"],
+        "Ok(Shorts { all: true, long_listing: true, name: None })",
+    );
+    check::<Shorts>(
+        &["-nfoo"],
+        "Ok(Shorts { all: false, long_listing: false, name: Some(\"foo\") })",
+    );
+    check::<Shorts>(
+        &["-n=foo"],
+        "Ok(Shorts { all: false, long_listing: false, name: Some(\"foo\") })",
+    );
+    check::<Shorts>(
+        &["-alnfoo"],
+        "Ok(Shorts { all: true, long_listing: true, name: Some(\"foo\") })",
+    );
+    check::<Shorts>(
+        &["--long-listing", "--name", "foo"],
+        "Ok(Shorts { all: false, long_listing: true, name: Some(\"foo\") })",
+    );
+
+    // `#[arg(count)]` counts the occurrences, also inside a cluster
+    check::<Counts>(&[], "Ok(Counts { verbose: 0, quiet: false })");
+    check::<Counts>(
+        &["-vvv", "--verbose", "-q"],
+        "Ok(Counts { verbose: 4, quiet: true })",
+    );
+    check::<Counts>(&["-vqv"], "Ok(Counts { verbose: 2, quiet: true })");
+    let counts = Counts::try_parse_from(vec!["-v"; 300]).unwrap();
+    assert_eq!(counts.verbose, u8::MAX);
+
+    // `#[arg(env)]` reads the missing arguments from the environment
+    // variables, that are named after the field with the prefix
+    std::env::set_var("PRB_LEVEL", "3");
+    std::env::set_var("PRB_JOBS", "2");
+    std::env::set_var("PRB_TAG", "a");
+    check::<Env>(&[], "Ok(Env { level: Some(3), jobs: 2, tag: [\"a\"] })");
+    check::<Env>(
+        &["--level", "5", "--tag", "b"],
+        "Ok(Env { level: Some(5), jobs: 2, tag: [\"b\"] })",
+    );
+    std::env::set_var("PRB_LEVEL", "x");
+    check::<Env>(
+        &[],
+        "Err(Lexopt(cannot parse argument \"x\": invalid digit found in string))",
+    );
+    std::env::remove_var("PRB_LEVEL");
+    std::env::remove_var("PRB_JOBS");
+    check::<Env>(&[], "Err(MissingArgument(\"--jobs\"))");
+    check_help::<Env>(&["-h"], "      --level <level>  [env: PRB_LEVEL]\n");
+    std::env::remove_var("PRB_TAG");
+    std::env::set_var("PLAIN_HOST", "localhost");
+    check::<PlainEnv>(&[], "Ok(PlainEnv { host: \"localhost\" })");
+    std::env::remove_var("PLAIN_HOST");
+
+    // the defaults are used when the arguments are missing from the
+    // command line and from the environment, and shown in the help
+    check::<Defaults>(
+        &[],
+        "Ok(Defaults { port: 8080, host: \"localhost\", level: 1 })",
+    );
+    check::<Defaults>(
+        &["--port", "80", "--host", "example.com", "--level", "3"],
+        "Ok(Defaults { port: 80, host: \"example.com\", level: 3 })",
+    );
+    std::env::set_var("DEFAULTS_LEVEL", "2");
+    check::<Defaults>(
+        &[],
+        "Ok(Defaults { port: 8080, host: \"localhost\", level: 2 })",
+    );
+    std::env::remove_var("DEFAULTS_LEVEL");
+    check_help::<Defaults>(
+        &["-h"],
+        "      --port <port>    [default: 8080]
+      --host <host>    [default: localhost]
+      --level <level>  [default: 1] [env: DEFAULTS_LEVEL]
+",
+    );
+
+    // `#[derive(ValueEnum)]` parses the unit variants by their names,
+    // and the possible values are in the error and in the help
+    check::<Output>(
+        &["--format", "JSON", "--level", "very-high"],
+        "Ok(Output { format: Json, level: Some(VeryHigh) })",
+    );
+    check::<Output>(
+        &["--format", "yml"],
+        "Ok(Output { format: Yaml, level: None })",
+    );
+    check::<Output>(
+        &["--format", "tsv"],
+        "Ok(Output { format: TabSeparated, level: None })",
+    );
+    check::<Output>(
+        &["--format", "xml"],
+        "Err(Lexopt(cannot parse argument \"xml\": expected one of json, yaml, tsv))",
+    );
+    check::<Output>(
+        &["--format", "json", "--level", "LOW"],
+        "Err(Lexopt(cannot parse argument \"LOW\": expected one of low, very-high))",
+    );
+    assert_eq!(<Level as ValueEnum>::POSSIBLE_VALUES, ["low", "very-high"]);
+    check_help::<Output>(
+        &["-h"],
+        "      --format <format>  [possible values: json, yaml, tsv]\n",
+    );
+
+    // the options of the `#[flatten]` fields are merged inside the
+    // parser of the struct and of the variant
+    check::<Outer>(
+        &["-vv", "--format", "json", "foo"],
+        "Ok(Outer { common: Common { verbose: 2, format: \"json\" }, target: \"foo\" })",
+    );
+    check::<Outer>(
+        &["foo", "-v"],
+        "Ok(Outer { common: Common { verbose: 1, format: \"text\" }, target: \"foo\" })",
+    );
+    check::<Outer>(&["foo", "--force"], "Err(Lexopt(invalid option '--force'))");
+    check::<Deploy>(
+        &["run", "-fv"],
+        "Ok(Deploy { command: Run { common: Common { verbose: 1, format: \"text\" }, force: true } })",
+    );
+    check_help::<Outer>(
+        &["-h"],
+        "Options:
+  -v, --verbose...
+      --format <format>  [default: text]
+",
+    );
+
+    // `default_expr` calls the items of the module, also when a field
+    // has the same name
+    check::<Jobs>(&[], "Ok(Jobs { jobs: 4 })");
+    check::<Jobs>(&["--jobs", "2"], "Ok(Jobs { jobs: 2 })");
+
+    // the long names are escaped inside the generated code
+    check::<Escaped>(
+        &["--dir\\name", "foo"],
+        "Ok(Escaped { dir: Some(\"foo\") })",
+    );
+
+    // the wrappers are matched also by their full path
+    check::<Paths>(&[], "Ok(Paths { name: None, tag: [] })");
+    check::<Paths>(
+        &["--tag", "a", "--tag", "b"],
+        "Ok(Paths { name: None, tag: [\"a\", \"b\"] })",
+    );
+
+    // `#[cli]` can be above the derive, its arguments are forwarded
+    assert_eq!(Above::NAME, "above");
+    assert_eq!(Above::VERSION, Some("0.1.0"));
+    check::<Above>(&["--dry_run"], "Ok(Above { dry_run: true })");
+    check::<Above>(&["-V"], "Err(DisplayVersion(\"above 0.1.0\\n\"))");
+    check_help::<Above>(&["-h"], "Usage: above [OPTIONS]\n");
+
+    // the derives do not add inherent items to the user types
+    check::<Inherent>(
+        &["--verbose", "install"],
+        "Ok(Inherent { command: Install { force: false }, verbose: true })",
+    );
+    assert!(Inherent::new(true).verbose);
+
+    // the fields can have the names of the variables of the generated code
+    check::<Locals>(
+        &[
+            "--parser", "lexopt", "--trace", "--arg", "a", "--value", "2",
+        ],
+        "Ok(Locals { parser: Some(\"lexopt\"), trace: true, arg: [\"a\"], value: 2 })",
+    );
+    check::<Locals>(
+        &[],
+        "Ok(Locals { parser: None, trace: false, arg: [], value: 1 })",
+    );
+
+    // the switches take a value only with `#[arg(bool_value)]`
+    check::<Switches>(
+        &["--verbose=false"],
+        "Err(Lexopt(unexpected argument for option '--verbose': \"false\"))",
+    );
+    check::<Colors>(&[], "Ok(Colors { color: false, pager: None })");
+    check::<Colors>(
+        &["--color=false", "--pager=true"],
+        "Ok(Colors { color: false, pager: Some(true) })",
+    );
+    check::<Colors>(
+        &["-c", "--pager"],
+        "Ok(Colors { color: true, pager: Some(true) })",
+    );
+    check::<Colors>(
+        &["--color=maybe"],
+        "Err(Lexopt(cannot parse argument \"maybe\": provided string was not `true` or `false`))",
+    );
+
+    // `#[cli]` without arguments keeps the default metadata
+    check::<EmptyCli>(&["--verbose"], "Ok(EmptyCli { verbose: true })");
+    check::<EmptyCliAbove>(&["--verbose"], "Ok(EmptyCliAbove { verbose: true })");
+    assert_eq!(EmptyCli::NAME, EmptyCliAbove::NAME);
+    assert_eq!(EmptyCli::VERSION, None);
+    let usage = format!("Usage: {} [OPTIONS]\n", EmptyCli::NAME);
+    check_help::<EmptyCli>(&["-h"], &usage);
+
+    // the generated code calls the trait items with the full path
+    check::<Shadowing>(&["--verbose"], "Ok(Shadowing { verbose: true })");
+    check_help::<Shadowing>(&["-h"], "      --verbose\n");
+    assert_eq!(Shadowing::display_args(false), "quiet");
+    assert_eq!(Shadowing { verbose: true }.flags(), 1);
+
+    // `command` returns the finished command, with the usage of all
+    // the subcommands and the prefix of the environment variables
+    let git = Git::command();
+    assert_eq!(git.usage, "git [OPTIONS] [COMMAND]");
+    assert_eq!(git.subcommands[0].usage, "git remote [OPTIONS] <COMMAND>");
+    assert_eq!(
+        git.subcommands[0].subcommands[0].usage,
+        "git remote add [OPTIONS] <name> <url>"
+    );
+    let env = Env::command();
+    let level = env.args.iter().find(|arg| arg.long_name == "level");
+    assert_eq!(level.unwrap().env, "PRB_LEVEL");
+    assert_eq!(Mock::command().usage, "mock");
+
+    // `SubCommand::parse` returns an error for an unknown name
+    let mut parser = ParserInfo::from_args(Vec::<String>::new());
+    let result = <Command as SubCommand>::parse(&mut parser, "remove");
+    assert_eq!(
+        format!("{result:?}"),
+        "Err(Lexopt(unexpected argument \"remove\"))"
+    );
+
+    // the prefix is added only to the names of the fields, while
+    // `#[arg(env = "...")]` is used as written
+    std::env::set_var("PRB_USER", "jane");
+    std::env::set_var("LEXOPT_TEST_HOST", "localhost");
+    std::env::set_var("PRB_LEXOPT_TEST_HOST", "example.com");
+    check::<Prefixed>(
+        &[],
+        "Ok(Prefixed { user: Some(\"jane\"), host: Some(\"localhost\") })",
+    );
+    std::env::remove_var("PRB_USER");
+    std::env::remove_var("LEXOPT_TEST_HOST");
+    std::env::remove_var("PRB_LEXOPT_TEST_HOST");
+    check_help::<Prefixed>(
+        &["-h"],
+        "      --user <user>  [env: PRB_USER]
+      --host <host>  [env: LEXOPT_TEST_HOST]
+",
+    );
+
+    // the switches read from the environment accept also `1` and `0`
+    check::<EnvSwitch>(&[], "Ok(EnvSwitch { sw: false })");
+    std::env::set_var("PRB_SW", "1");
+    check::<EnvSwitch>(&[], "Ok(EnvSwitch { sw: true })");
+    std::env::set_var("PRB_SW", "0");
+    check::<EnvSwitch>(&[], "Ok(EnvSwitch { sw: false })");
+    check::<EnvSwitch>(&["--sw"], "Ok(EnvSwitch { sw: true })");
+    std::env::set_var("PRB_SW", "true");
+    check::<EnvSwitch>(&[], "Ok(EnvSwitch { sw: true })");
+    std::env::set_var("PRB_SW", "on");
+    check::<EnvSwitch>(
+        &[],
+        "Err(Lexopt(cannot parse argument \"on\": provided string was not `true` or `false`))",
+    );
+    std::env::remove_var("PRB_SW");
+
+    // `default_expr` can refer to `Self`, that is the struct or the enum
+    check::<Server>(
+        &[],
+        "Ok(Server { port: 8080, host: \"localhost\", command: None })",
+    );
+    check::<Server>(
+        &["start"],
+        "Ok(Server { port: 8080, host: \"localhost\", command: Some(Start { workers: 4 }) })",
+    );
+    check_help::<Server>(&["-h"], "      --port <port>  [default: 8080]\n");
+    check_help::<Server>(
+        &["start", "-h"],
+        "      --workers <workers>  [default: 4]\n",
+    );
+
+    // the builtin `-h` and `-V` leave the names used by the flattened parsers
+    check::<Remote>(
+        &["-h", "example.com", "-V"],
+        "Ok(Remote { conn: Conn { host: Some(\"example.com\"), verify: true }, command: None })",
+    );
+    check::<Remote>(
+        &["ping", "-h", "example.com"],
+        "Ok(Remote { conn: Conn { host: None, verify: false }, command: Some(Ping { conn: Conn { host: Some(\"example.com\"), verify: false } }) })",
+    );
+    check_help::<Remote>(
+        &["--help"],
+        "Options:
+  -h, --host <host>
+  -V, --verify
+      --help         Print help
+      --version      Print version
+",
+    );
+    check_help::<Remote>(&["ping", "--help"], "      --help         Print help\n");
+    check_output("remote", &["--version"], "remote 0.1.0");
+}
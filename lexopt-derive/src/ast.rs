@@ -0,0 +1,530 @@
+//! AST of the items where the derive macros are applied.
+//!
+//! The AST that kproc-parser gives us drops the attributes of the
+//! enum values and the generics of the types, so we parse here
+//! only the information that the derive macros need.
+use std::fmt;
+
+use kproc_parser::kproc_macros::KTokenStream;
+use kproc_parser::proc_macro::{Delimiter, TokenStream, TokenTree};
+
+use crate::error::MacroError;
+
+pub enum ItemNode {
+    Struct(StructNode),
+    Enum(EnumNode),
+}
+
+pub struct StructNode {
+    pub attrs: Vec<AttrNode>,
+    pub identifier: TokenTree,
+    pub fields: Vec<FieldNode>,
+}
+
+pub struct EnumNode {
+    pub attrs: Vec<AttrNode>,
+    pub identifier: TokenTree,
+    pub values: Vec<VariantNode>,
+}
+
+pub struct VariantNode {
+    pub attrs: Vec<AttrNode>,
+    pub identifier: TokenTree,
+    pub kind: VariantKind,
+}
+
+pub enum VariantKind {
+    /// `Install { name: String }`
+    Named(Vec<FieldNode>),
+    /// `Install(InstallArgs)`
+    Unnamed(Vec<TyNode>),
+    /// `Install`
+    Unit,
+}
+
+pub struct FieldNode {
+    pub attrs: Vec<AttrNode>,
+    pub identifier: TokenTree,
+    pub ty: TyNode,
+}
+
+#[derive(Clone)]
+pub struct TyNode {
+    pub tokens: Vec<TokenTree>,
+}
+
+/// An outer attribute like `#[name]`, `#[name(args)]` or
+/// `#[name = value]`.
+pub struct AttrNode {
+    pub name: TokenTree,
+    pub args: Option<TokenStream>,
+    /// The value of `#[name = value]`, like the `#[doc = "..."]`
+    /// generated by the doc comments.
+    pub value: Option<TokenTree>,
+}
+
+/// The doc comments of an item, the first paragraph is the short
+/// help and the other paragraphs are the long help.
+#[derive(Default)]
+pub struct DocNode {
+    pub short: String,
+    pub long: String,
+}
+
+/// A single argument inside an attribute, like `short = 'v'`
+/// or `positional`.
+pub struct AttrArg {
+    pub key: TokenTree,
+    pub value: Option<Vec<TokenTree>>,
+}
+
+impl StructNode {
+    pub fn attr_args(&self, name: &str) -> Result<Vec<AttrArg>, MacroError> {
+        attr_args(&self.attrs, name)
+    }
+
+    pub fn doc(&self) -> DocNode {
+        DocNode::from_attrs(&self.attrs)
+    }
+}
+
+impl EnumNode {
+    pub fn attr_args(&self, name: &str) -> Result<Vec<AttrArg>, MacroError> {
+        attr_args(&self.attrs, name)
+    }
+}
+
+impl VariantNode {
+    pub fn attr_args(&self, name: &str) -> Result<Vec<AttrArg>, MacroError> {
+        attr_args(&self.attrs, name)
+    }
+
+    pub fn doc(&self) -> DocNode {
+        DocNode::from_attrs(&self.attrs)
+    }
+}
+
+impl FieldNode {
+    pub fn has_attr(&self, name: &str) -> bool {
+        self.attrs.iter().any(|attr| attr.name.to_string() == name)
+    }
+
+    /// Return all the arguments of the attributes with the given name,
+    /// e.g. all the `#[arg(...)]` of the field.
+    pub fn attr_args(&self, name: &str) -> Result<Vec<AttrArg>, MacroError> {
+        attr_args(&self.attrs, name)
+    }
+
+    pub fn doc(&self) -> DocNode {
+        DocNode::from_attrs(&self.attrs)
+    }
+
+    /// The name of the field without the `r#` prefix of the raw
+    /// identifiers.
+    pub fn name(&self) -> String {
+        let name = self.identifier.to_string();
+        name.strip_prefix("r#").unwrap_or(&name).to_owned()
+    }
+}
+
+impl fmt::Display for FieldNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.identifier, self.ty)
+    }
+}
+
+impl TyNode {
+    /// Return the type inside the `wrapper` generic type, e.g. `String`
+    /// for `Option<String>` when the wrapper is `Option`.
+    ///
+    /// The wrapper is the last segment of the path, so also
+    /// `std::option::Option<String>` is matched.
+    pub fn inner_of(&self, wrapper: &str) -> Option<TyNode> {
+        let open = self
+            .tokens
+            .iter()
+            .position(|token| token.to_string() == "<")?;
+        let ([path @ .., ident], [_, inner @ .., close]) = self.tokens.split_at(open) else {
+            return None;
+        };
+        let is_path = path.iter().all(|token| match token {
+            TokenTree::Ident(_) => true,
+            TokenTree::Punct(punct) => punct.as_char() == ':',
+            _ => false,
+        });
+        if !is_path || ident.to_string() != wrapper || close.to_string() != ">" {
+            return None;
+        }
+        Some(TyNode {
+            tokens: inner.to_vec(),
+        })
+    }
+}
+
+impl fmt::Display for TyNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stream: TokenStream = self.tokens.iter().cloned().collect();
+        write!(f, "{stream}")
+    }
+}
+
+impl DocNode {
+    /// Collect the `#[doc = "..."]` attributes, the lines of a paragraph
+    /// are joined with a space, like the text of a markdown paragraph.
+    pub fn from_attrs(attrs: &[AttrNode]) -> Self {
+        let mut paragraphs: Vec<Vec<String>> = vec![vec![]];
+        let lines = attrs
+            .iter()
+            .filter(|attr| attr.name.to_string() == "doc")
+            .filter_map(|attr| attr.value.as_ref())
+            .filter_map(|value| literal_content(&value.to_string()))
+            .flat_map(|doc| doc.split('\n').map(str::to_owned).collect::<Vec<_>>());
+        for line in lines {
+            let line = line.trim();
+            match line.is_empty() {
+                true => paragraphs.push(vec![]),
+                false => paragraphs.last_mut().unwrap().push(line.to_owned()),
+            }
+        }
+        let mut paragraphs = paragraphs
+            .into_iter()
+            .filter(|lines| !lines.is_empty())
+            .map(|lines| lines.join(" "));
+        DocNode {
+            short: paragraphs.next().unwrap_or_default(),
+            long: paragraphs.collect::<Vec<_>>().join("\n\n"),
+        }
+    }
+}
+
+impl AttrNode {
+    /// Parse the list of arguments, a bare attribute like `#[cli]`
+    /// has an empty list.
+    pub fn parse_args(&self) -> Result<Vec<AttrArg>, MacroError> {
+        match (&self.args, &self.value) {
+            (Some(args), _) => parse_attr_args(args),
+            (None, None) => Ok(vec![]),
+            (None, Some(_)) => Err(build_error!(
+                self.name.clone(),
+                "expected a list of arguments"
+            )),
+        }
+    }
+}
+
+/// Parse the arguments of an attribute, like `short = 'v', positional`.
+pub fn parse_attr_args(args: &TokenStream) -> Result<Vec<AttrArg>, MacroError> {
+    let mut result = vec![];
+    for tokens in split_commas(args.clone(), false) {
+        let key = tokens[0].clone();
+        let value = match tokens.get(1) {
+            Some(tok) if tok.to_string() == "=" && tokens.len() > 2 => Some(tokens[2..].to_vec()),
+            Some(tok) => return Err(build_error!(tok.clone(), "expected `key = value`")),
+            None => None,
+        };
+        result.push(AttrArg { key, value });
+    }
+    Ok(result)
+}
+
+impl AttrArg {
+    fn single_value(&self) -> Result<TokenTree, MacroError> {
+        match self.value.as_deref() {
+            Some([value]) => Ok(value.clone()),
+            Some([_, tok, ..]) => Err(build_error!(tok.clone(), "expected a single literal")),
+            _ => Err(build_error!(self.key.clone(), "missing value")),
+        }
+    }
+
+    /// Return the content of a string literal value, like
+    /// `verbose-mode` for `long = "verbose-mode"`.
+    pub fn str_value(&self) -> Result<String, MacroError> {
+        let value = self.single_value()?;
+        let Some(content) = literal_content(&value.to_string()) else {
+            return Err(build_error!(value, "expected a string literal"));
+        };
+        Ok(content)
+    }
+
+    /// Return the integer literal value, like `1` for `index = 1`.
+    pub fn usize_value(&self) -> Result<usize, MacroError> {
+        let value = self.single_value()?;
+        let literal = value.to_string();
+        literal
+            .strip_suffix("usize")
+            .unwrap_or(&literal)
+            .parse()
+            .map_err(|_| build_error!(value, "expected an integer literal"))
+    }
+
+    /// Return the char literal value, like `'v'` for `short = 'v'`.
+    pub fn char_value(&self) -> Result<TokenTree, MacroError> {
+        let value = self.single_value()?;
+        if !value.to_string().starts_with('\'') {
+            return Err(build_error!(value, "expected a char literal"));
+        }
+        Ok(value)
+    }
+}
+
+/// Parse the struct or the enum where the derive macro is applied.
+pub fn parse_item(stream: &TokenStream) -> Result<ItemNode, MacroError> {
+    let mut stream = KTokenStream::new(stream);
+    let attrs = parse_attrs(&mut stream)?;
+    skip_visibility(&mut stream);
+    let keyword = stream.advance();
+    if stream.is_end() {
+        return Err(build_error!(keyword, "expected an identifier"));
+    }
+    let identifier = stream.advance();
+    if stream.is_end() {
+        return Err(build_error!(identifier, "expected the item body"));
+    }
+    if stream.match_tok("<") {
+        return Err(build_error!(
+            stream.peek().clone(),
+            "generics are not supported"
+        ));
+    }
+    let body = match stream.advance() {
+        TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => group.stream(),
+        tok => return Err(build_error!(tok, "expected the item body")),
+    };
+    match keyword.to_string().as_str() {
+        "struct" => Ok(ItemNode::Struct(StructNode {
+            attrs,
+            identifier,
+            fields: parse_fields(body)?,
+        })),
+        "enum" => Ok(ItemNode::Enum(EnumNode {
+            attrs,
+            identifier,
+            values: parse_variants(body)?,
+        })),
+        _ => Err(build_error!(keyword, "only struct and enum are supported")),
+    }
+}
+
+fn attr_args(attrs: &[AttrNode], name: &str) -> Result<Vec<AttrArg>, MacroError> {
+    let mut args = vec![];
+    for attr in attrs.iter() {
+        if attr.name.to_string() == name {
+            args.append(&mut attr.parse_args()?);
+        }
+    }
+    Ok(args)
+}
+
+fn parse_attrs(stream: &mut KTokenStream) -> Result<Vec<AttrNode>, MacroError> {
+    let mut attrs = vec![];
+    while !stream.is_end() && stream.match_tok("#") {
+        let pound = stream.advance();
+        let attr = match stream.is_end() {
+            false => stream.advance(),
+            true => return Err(build_error!(pound, "expected an attribute")),
+        };
+        let TokenTree::Group(group) = attr else {
+            return Err(build_error!(attr, "expected an attribute"));
+        };
+        let mut inner = KTokenStream::new(&group.stream());
+        let mut name = inner.advance();
+        // `#[lexopt_derive::cli]` is the same of `#[cli]`, so the
+        // attributes work also without importing them.
+        while !inner.is_end() && inner.match_tok(":") {
+            let _ = inner.advance();
+            let _ = inner.advance();
+            if !inner.is_end() {
+                name = inner.advance();
+            }
+        }
+        let mut args = None;
+        let mut value = None;
+        if !inner.is_end() {
+            match inner.advance() {
+                TokenTree::Group(group) => args = Some(group.stream()),
+                TokenTree::Punct(punct) if punct.as_char() == '=' && !inner.is_end() => {
+                    value = Some(inner.advance())
+                }
+                _ => {}
+            }
+        }
+        attrs.push(AttrNode { name, args, value });
+    }
+    Ok(attrs)
+}
+
+fn skip_visibility(stream: &mut KTokenStream) {
+    if stream.is_end() || !stream.match_tok("pub") {
+        return;
+    }
+    let _ = stream.advance();
+    if stream.is_end() {
+        return;
+    }
+    if let TokenTree::Group(group) = stream.peek() {
+        // pub(crate), pub(super), ...
+        if group.delimiter() == Delimiter::Parenthesis {
+            let _ = stream.advance();
+        }
+    }
+}
+
+fn parse_fields(body: TokenStream) -> Result<Vec<FieldNode>, MacroError> {
+    let mut fields = vec![];
+    for tokens in split_commas(body, true) {
+        let mut stream = KTokenStream::new(&tokens.into_iter().collect());
+        let attrs = parse_attrs(&mut stream)?;
+        skip_visibility(&mut stream);
+        let identifier = stream.advance();
+        if stream.is_end() || !stream.match_tok(":") {
+            return Err(build_error!(
+                identifier,
+                "expected `:` after the field name"
+            ));
+        }
+        let colon = stream.advance();
+        let mut ty = vec![];
+        while !stream.is_end() {
+            ty.push(stream.advance());
+        }
+        if ty.is_empty() {
+            return Err(build_error!(colon, "expected the field type"));
+        }
+        fields.push(FieldNode {
+            attrs,
+            identifier,
+            ty: TyNode { tokens: ty },
+        });
+    }
+    Ok(fields)
+}
+
+fn parse_variants(body: TokenStream) -> Result<Vec<VariantNode>, MacroError> {
+    let mut values = vec![];
+    for tokens in split_commas(body, false) {
+        let mut stream = KTokenStream::new(&tokens.into_iter().collect());
+        let attrs = parse_attrs(&mut stream)?;
+        let identifier = stream.advance();
+        let kind = match stream.is_end() {
+            true => VariantKind::Unit,
+            false => match stream.advance() {
+                TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => {
+                    VariantKind::Named(parse_fields(group.stream())?)
+                }
+                TokenTree::Group(group) if group.delimiter() == Delimiter::Parenthesis => {
+                    let types = split_commas(group.stream(), true)
+                        .into_iter()
+                        .map(|tokens| TyNode { tokens })
+                        .collect();
+                    VariantKind::Unnamed(types)
+                }
+                // a discriminant, like `Install = 1`
+                _ => VariantKind::Unit,
+            },
+        };
+        values.push(VariantNode {
+            attrs,
+            identifier,
+            kind,
+        });
+    }
+    Ok(values)
+}
+
+/// Return the content of a string literal with the escapes resolved,
+/// or `None` if the literal is not a string.
+fn literal_content(literal: &str) -> Option<String> {
+    if let Some(raw) = literal.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        if !raw[hashes..].starts_with('"') {
+            return None;
+        }
+        let content = raw.get(hashes + 1..raw.len().checked_sub(hashes + 1)?)?;
+        return Some(content.to_owned());
+    }
+    let content = literal.strip_prefix('"')?.strip_suffix('"')?;
+    let mut result = String::new();
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            't' => result.push('\t'),
+            '0' => result.push('\0'),
+            'x' => {
+                let code: String = chars.by_ref().take(2).collect();
+                result.push(u8::from_str_radix(&code, 16).ok()? as char);
+            }
+            'u' => {
+                let code: String = chars.by_ref().skip(1).take_while(|c| *c != '}').collect();
+                result.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+            }
+            // a line continuation skips the whitespaces of the next line
+            '\n' => while chars.next_if(|c| c.is_whitespace()).is_some() {},
+            c => result.push(c),
+        }
+    }
+    Some(result)
+}
+
+/// Split the stream on the top level commas, when `generics` is
+/// true the commas inside `<...>` are not considered, so a field
+/// type like `HashMap<String, String>` stays in one piece.
+fn split_commas(stream: TokenStream, generics: bool) -> Vec<Vec<TokenTree>> {
+    let mut result = vec![vec![]];
+    let mut depth = 0;
+    for tok in stream {
+        if let TokenTree::Punct(ref punct) = tok {
+            match punct.as_char() {
+                '<' if generics => depth += 1,
+                '>' if generics && depth > 0 => depth -= 1,
+                ',' if depth == 0 => {
+                    result.push(vec![]);
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        result.last_mut().unwrap().push(tok);
+    }
+    result.retain(|tokens| !tokens.is_empty());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::literal_content;
+
+    #[test]
+    fn literal_content_of_strings() {
+        assert_eq!(literal_content(r#""es""#).as_deref(), Some("es"));
+        assert_eq!(literal_content(r#""""#).as_deref(), Some(""));
+        assert_eq!(literal_content(r#""a \"b\"""#).as_deref(), Some(r#"a "b""#));
+        assert_eq!(
+            literal_content(r#""a\nb\t\\""#).as_deref(),
+            Some("a\nb\t\\")
+        );
+        assert_eq!(literal_content(r#""\x41\u{e9}""#).as_deref(), Some("Aé"));
+        assert_eq!(literal_content("\"a \\\n    b\"").as_deref(), Some("a b"));
+    }
+
+    #[test]
+    fn literal_content_of_raw_strings() {
+        assert_eq!(literal_content(r#"r"a\n""#).as_deref(), Some(r"a\n"));
+        assert_eq!(
+            literal_content(r###"r#"say "hi""#"###).as_deref(),
+            Some(r#"say "hi""#)
+        );
+    }
+
+    #[test]
+    fn literal_content_of_other_literals() {
+        assert_eq!(literal_content("42"), None);
+        assert_eq!(literal_content("'v'"), None);
+        assert_eq!(literal_content("true"), None);
+    }
+}
@@ -1,88 +1,164 @@
-use kproc_parser::kparser::{KParserError, KParserTracer};
-use kproc_parser::kproc_macros::KTokenStream;
-use kproc_parser::proc_macro::{TokenStream, TokenTree};
-use kproc_parser::rust::ast_nodes::StructToken;
-
-use kproc_parser::{build_error, check};
+use kproc_parser::kparser::KParserTracer;
+use kproc_parser::proc_macro::{
+    Delimiter, Group, Ident, Punct, Spacing, Span, TokenStream, TokenTree,
+};
+use kproc_parser::trace;
 
+use crate::ast::{parse_attr_args, parse_item, ItemNode};
+use crate::error::MacroError;
 use crate::Tracer;
 
 pub struct CliHelper {
-    pub name: TokenTree,
-    pub about: TokenTree,
-    pub version: Option<TokenTree>,
-    pub author: Option<TokenTree>,
-    pub on_ty: Option<TokenTree>,
+    pub name: Option<String>,
+    pub about: Option<String>,
+    pub version: Option<String>,
+    pub author: Option<String>,
+    pub on_ty: TokenTree,
     pub item: TokenStream,
 }
 
 impl CliHelper {
-    pub fn to_token_stream(self) -> TokenStream {
+    fn new(on_ty: TokenTree, item: TokenStream) -> Self {
+        CliHelper {
+            name: None,
+            about: None,
+            version: None,
+            author: None,
+            on_ty,
+            item,
+        }
+    }
+
+    pub fn into_token_stream(self) -> TokenStream {
         self.to_string().parse().unwrap()
     }
 
     fn parse(
-        stream: &mut KTokenStream,
-        _: &dyn KParserTracer,
-        ast: &StructToken,
+        attribute: &TokenStream,
+        tracer: &dyn KParserTracer,
         item: TokenStream,
-    ) -> Result<Self, KParserError> {
-        let mut name: Option<TokenTree> = Some(ast.name.clone());
-        let mut about: Option<TokenTree> = None;
-        let mut version: Option<TokenTree> = None;
-        let mut author: Option<TokenTree> = None;
-
-        let mut last_token: Option<TokenTree> = None;
-        while !stream.is_end() {
-            let key = stream.advance();
-            check!("=", stream.peek())?;
-            let _ = stream.advance();
-            let value = stream.advance();
-            if !stream.is_end() && stream.match_tok(",") {
-                check!(",", stream.advance())?;
+    ) -> Result<Self, MacroError> {
+        let ItemNode::Struct(ast) = parse_item(&item)? else {
+            let tok = item.clone().into_iter().next().unwrap();
+            return Err(build_error!(tok, "cli can be used only on a struct"));
+        };
+        let mut info = Self::new(ast.identifier, forward_args(item, attribute));
+        for arg in parse_attr_args(attribute)? {
+            trace!(tracer, "cli argument: {}", arg.key);
+            match arg.key.to_string().as_str() {
+                "name" => info.name = Some(arg.str_value()?),
+                "about" => info.about = Some(arg.str_value()?),
+                "version" => info.version = Some(arg.str_value()?),
+                "author" => info.author = Some(arg.str_value()?),
+                // used by the `Parser` derive macro
+                "rename_all" | "env_prefix" => {
+                    arg.str_value()?;
+                }
+                // used by the `Parser` derive macro, it is a flag without value
+                "debug_expand" => {}
+                _ => return Err(build_error!(arg.key, "unknown `cli` attribute")),
             }
-
-            match key.to_string().as_str() {
-                "name" => name = Some(value),
-                "about" => about = Some(value),
-                "version" => version = Some(value),
-                "author" => author = Some(value),
-                _ => return Err(build_error!(key, "cli value not found")),
-            };
-            last_token = Some(key);
         }
-        Ok(Self {
-            name: name.ok_or(build_error!(
-                last_token.clone().unwrap(),
-                "name must be specified"
-            ))?,
-            about: about.ok_or(build_error!(
-                last_token.clone().unwrap(),
-                "about must be specified"
-            ))?,
-            version,
-            author,
-            on_ty: None,
-            item,
-        })
+        Ok(info)
+    }
+}
+/// Copy the arguments of `cli` inside the inert `#[cli_meta(...)]` after
+/// `#[derive(Parser)]`, so the derive macro reads them also when `cli`
+/// is above the derive, and it is expanded and removed before it.
+fn forward_args(item: TokenStream, attribute: &TokenStream) -> TokenStream {
+    let mut tokens = vec![];
+    let mut forwarded = false;
+    for token in item {
+        let is_derive = match token {
+            TokenTree::Group(ref group) => {
+                group.delimiter() == Delimiter::Bracket && is_parser_derive(group.stream())
+            }
+            _ => false,
+        };
+        tokens.push(token);
+        if is_derive && !forwarded {
+            forwarded = true;
+            let args = Group::new(Delimiter::Parenthesis, attribute.clone());
+            let meta = [
+                TokenTree::Ident(Ident::new("cli_meta", Span::call_site())),
+                TokenTree::Group(args),
+            ];
+            tokens.push(TokenTree::Punct(Punct::new('#', Spacing::Alone)));
+            tokens.push(TokenTree::Group(Group::new(
+                Delimiter::Bracket,
+                meta.into_iter().collect(),
+            )));
+        }
+    }
+    tokens.into_iter().collect()
+}
+
+/// Return true if the content of an attribute is `derive(...)`
+/// with `Parser` inside the list.
+fn is_parser_derive(attr: TokenStream) -> bool {
+    let mut tokens = attr.into_iter();
+    let Some(TokenTree::Ident(name)) = tokens.next() else {
+        return false;
+    };
+    let Some(TokenTree::Group(list)) = tokens.next() else {
+        return false;
+    };
+    name.to_string() == "derive"
+        && list
+            .stream()
+            .into_iter()
+            .any(|token| token.to_string() == "Parser")
+}
+
+/// Generate the expression of an optional metadata value.
+fn optional_str(value: &Option<String>) -> String {
+    match value {
+        Some(value) => format!("::core::option::Option::Some({value:?})"),
+        None => "::core::option::Option::None".to_owned(),
     }
 }
 
 impl std::fmt::Display for CliHelper {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.item)
+        writeln!(f, "{}", self.item)?;
+        let ty = &self.on_ty;
+        let name = match self.name {
+            Some(ref name) => format!("{name:?}"),
+            None => "::core::env!(\"CARGO_PKG_NAME\")".to_owned(),
+        };
+        write!(
+            f,
+            "impl {ty} {{
+                /// The name of the command.
+                pub const NAME: &'static str = {name};
+                /// The description of the command.
+                pub const ABOUT: ::core::option::Option<&'static str> = {};
+                /// The version printed by `-V/--version`.
+                pub const VERSION: ::core::option::Option<&'static str> = {};
+                /// The author of the command.
+                pub const AUTHOR: ::core::option::Option<&'static str> = {};
+            }}",
+            optional_str(&self.about),
+            optional_str(&self.version),
+            optional_str(&self.author),
+        )
     }
 }
 
-pub fn parse(tokens: TokenStream, struct_ast: StructToken, raw_item: TokenStream) -> TokenStream {
+pub fn parse(attribute: TokenStream, item: TokenStream) -> TokenStream {
     let tracer = Tracer {};
-    let mut stream = KTokenStream::new(&tokens);
-    let info = CliHelper::parse(&mut stream, &tracer, &struct_ast, raw_item);
-    if let Err(err) = info {
-        err.emit();
-        panic!()
+    match CliHelper::parse(&attribute, &tracer, item.clone()) {
+        Ok(info) => info.into_token_stream(),
+        Err(err) => {
+            // keep the item and the default metadata, so the
+            // error is only the one of the attribute
+            let expansion = match parse_item(&item) {
+                Ok(ItemNode::Struct(ast)) => {
+                    CliHelper::new(ast.identifier, item).into_token_stream()
+                }
+                _ => item,
+            };
+            [err.to_compile_error(), expansion].into_iter().collect()
+        }
     }
-    let mut info = info.unwrap();
-    info.on_ty = Some(struct_ast.name);
-    info.to_token_stream()
 }
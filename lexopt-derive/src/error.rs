@@ -0,0 +1,50 @@
+//! Errors of the procedural macros, they are reported with a
+//! `compile_error!` that points to the token that caused them.
+use kproc_parser::proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span};
+use kproc_parser::proc_macro::{TokenStream, TokenTree};
+
+#[derive(Debug)]
+pub struct MacroError {
+    pub tok: TokenTree,
+    pub msg: String,
+}
+
+impl MacroError {
+    pub fn new(tok: TokenTree, msg: &str) -> Self {
+        MacroError {
+            tok,
+            msg: msg.to_owned(),
+        }
+    }
+
+    /// Generate `::core::compile_error! { "msg" }` with the span of
+    /// the token, so the compiler shows the error on the user code.
+    pub fn to_compile_error(&self) -> TokenStream {
+        let span = self.tok.span();
+        let mut message = TokenTree::Literal(Literal::string(&self.msg));
+        message.set_span(span);
+        let tokens = [
+            punct(':', Spacing::Joint, span),
+            punct(':', Spacing::Alone, span),
+            TokenTree::Ident(Ident::new("core", span)),
+            punct(':', Spacing::Joint, span),
+            punct(':', Spacing::Alone, span),
+            TokenTree::Ident(Ident::new("compile_error", span)),
+            punct('!', Spacing::Alone, span),
+            group(Delimiter::Brace, message.into(), span),
+        ];
+        tokens.into_iter().collect()
+    }
+}
+
+fn punct(ch: char, spacing: Spacing, span: Span) -> TokenTree {
+    let mut punct = Punct::new(ch, spacing);
+    punct.set_span(span);
+    TokenTree::Punct(punct)
+}
+
+fn group(delimiter: Delimiter, stream: TokenStream, span: Span) -> TokenTree {
+    let mut group = Group::new(delimiter, stream);
+    group.set_span(span);
+    TokenTree::Group(group)
+}
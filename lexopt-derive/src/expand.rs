@@ -0,0 +1,62 @@
+//! Dump of the code generated by the derive macros, so it is
+//! possible to inspect it without `cargo expand`.
+//!
+//! The dump is enabled with `#[cli(debug_expand)]` on the parser, or
+//! for all the derives with the `LEXOPT_DEBUG_EXPAND` environment
+//! variable, and it is stored inside `target/lexopt-expand/<Type>.rs`.
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Return true if the `LEXOPT_DEBUG_EXPAND` environment variable
+/// enables the dump of all the derives.
+pub fn is_enabled() -> bool {
+    env::var_os("LEXOPT_DEBUG_EXPAND").is_some_and(|value| value != "0")
+}
+
+/// Write the generated code of the type inside the target directory,
+/// formatted with `rustfmt` when it is available.
+pub fn dump(identifier: &str, code: &str) {
+    let dir = target_dir().join("lexopt-expand");
+    let path = dir.join(format!("{identifier}.rs"));
+    if let Err(err) = fs::create_dir_all(&dir).and_then(|_| fs::write(&path, code)) {
+        eprintln!("lexopt-derive: unable to write {}: {err}", path.display());
+        return;
+    }
+    // the code is still useful when it is not formatted
+    let _ = Command::new("rustfmt")
+        .args(["--edition", "2021"])
+        .arg(&path)
+        .stderr(Stdio::null())
+        .status();
+}
+
+/// The target directory of the crate that is compiled, that is
+/// `CARGO_TARGET_DIR` or the closest `target` directory.
+fn target_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("CARGO_TARGET_DIR") {
+        return PathBuf::from(dir);
+    }
+    let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default());
+    manifest_dir
+        .ancestors()
+        .map(|dir| dir.join("target"))
+        .find(|dir| dir.is_dir())
+        .unwrap_or_else(|| manifest_dir.join("target"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    #[test]
+    fn dump_writes_inside_the_target_dir() {
+        let dir = env::temp_dir().join(format!("lexopt-expand-{}", process::id()));
+        env::set_var("CARGO_TARGET_DIR", &dir);
+        super::dump("Dumped", "struct Dumped;");
+        let code = fs::read_to_string(dir.join("lexopt-expand/Dumped.rs")).unwrap();
+        assert!(code.contains("struct Dumped;"), "{code}");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
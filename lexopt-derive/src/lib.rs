@@ -1,71 +1,84 @@
 use kproc_parser::kparser::KParserTracer;
 use kproc_parser::proc_macro::TokenStream;
-use kproc_parser::rust::kparser::RustParser;
 
+/// Build a `MacroError` that points to the token, it has the same
+/// interface of the `build_error!` of kproc-parser.
+macro_rules! build_error {
+    ($tok:expr, $msg:expr) => {
+        crate::error::MacroError::new($tok, $msg)
+    };
+}
+
+mod ast;
 mod cli;
+mod error;
+mod expand;
 mod help;
 mod parser;
+mod rename;
 mod subcommand;
+mod value_enum;
 
 use cli as cli_parser;
 
 const TRACER: Tracer = Tracer {};
 
+/// The path of the runtime used by the generated code, that uses only
+/// absolute paths to not depend on the imports of the user module.
+const RUNTIME: &str = "::lexopt_cli::prelude";
+
+/// Log of the derive macros while they parse the items, enabled
+/// with the `LEXOPT_DERIVE_TRACE` environment variable.
 struct Tracer;
 
 impl KParserTracer for Tracer {
     fn log(&self, msg: &str) {
-        eprintln!("\x1b[93mproc_macro\x1b[0m: {msg}");
-    }
-}
-
-pub(crate) mod macros {
-    macro_rules! build_parser {
-        ($tracer:expr) => {{
-            use kproc_parser::rust::kparser::RustParser;
-
-            RustParser::with_tracer(&$tracer)
-        }};
+        if std::env::var_os("LEXOPT_DERIVE_TRACE").is_some() {
+            eprintln!("\x1b[93mproc_macro\x1b[0m: {msg}");
+        }
     }
-    pub(crate) use build_parser;
 }
 
-#[proc_macro_derive(Parser, attributes(subcommand))]
+/// Derive the command line parser of a struct.
+#[proc_macro_derive(Parser, attributes(subcommand, arg, flatten, cli_meta))]
 pub fn parser(tokens: TokenStream) -> TokenStream {
     parser::parse(tokens)
 }
 
-#[proc_macro_derive(SubCommand, attributes(subcommand))]
+/// Derive the subcommands of an enum, each variant is a subcommand.
+#[proc_macro_derive(SubCommand, attributes(subcommand, arg, flatten))]
 pub fn subcommand(tokens: TokenStream) -> TokenStream {
     subcommand::parse(tokens)
 }
 
+/// Derive `FromStr` for an enum with unit variants, so it can be
+/// used as the value of an argument with `#[arg(value_enum)]`.
+#[proc_macro_derive(ValueEnum, attributes(value))]
+pub fn value_enum(tokens: TokenStream) -> TokenStream {
+    value_enum::parse(tokens)
+}
+
 /// cli procedural macro attribute
 ///
+/// Attach the metadata of the command to the struct, the `Parser`
+/// derive macro use them inside the help, and generate `-V/--version`
+/// when the version is specified.
+///
+/// The attribute can be above or below `#[derive(Parser)]`, the
+/// arguments are copied inside `#[cli_meta(...)]` for the derive.
+///
 /// EXPAND:
 /// ```ignore
-/// pub struct ParserInfo {
-///     name: String,
-///     about: String,
-///     version: String,
-///     author: String,
-///     command_map: HashMap<String, DisplayCommand>,
-/// }
-///
-/// impl ParserInfo {
-///     pub fn new() -> Self {
-///         ParserInfo {
-///             command_map: HashMap::new(),
-///         }
-///     }
+/// impl CliArgs {
+///     pub const NAME: &'static str = "es";
+///     pub const ABOUT: Option<&'static str> = Some("...");
+///     pub const VERSION: Option<&'static str> = Some("0.0.1");
+///     pub const AUTHOR: Option<&'static str> = None;
 /// }
 /// ````
 #[proc_macro_attribute]
 pub fn cli(attribute: TokenStream, item: TokenStream) -> TokenStream {
-    let parser = RustParser::new();
-    // the item is always on a parser struct.
-    let ast = parser.parse_struct(&item);
-    cli_parser::parse(attribute, ast, item)
+    cli_parser::parse(attribute, item)
 }
 
 #[proc_macro_attribute]
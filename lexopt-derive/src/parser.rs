@@ -1,172 +1,1221 @@
-use kproc_parser::kparser::{KParserError, KParserTracer};
+use kproc_parser::kparser::KParserTracer;
 use kproc_parser::proc_macro::TokenStream;
-use kproc_parser::proc_macro::TokenTree;
-use kproc_parser::rust::ast_nodes::TopLevelNode;
+use kproc_parser::proc_macro::{Literal, TokenTree};
 use kproc_parser::trace;
 
-use crate::macros::build_parser;
-use crate::TRACER;
+use crate::ast::{parse_item, DocNode, FieldNode, ItemNode, TyNode};
+use crate::error::MacroError;
+use crate::expand;
+use crate::rename::{rename_all_variant, RenamedNames};
+use crate::{RUNTIME, TRACER};
 
 pub struct ParserMacroInfo {
     pub identifier: Option<TokenTree>,
+    /// The doc comment of the struct.
+    pub doc: DocNode,
+    /// The struct has the `#[cli(...)]` attribute, so the metadata
+    /// of the command is inside the consts generated by it.
+    pub cli: bool,
+    /// The version is set with `#[cli(version = "...")]`, so
+    /// `-V/--version` is generated.
+    pub version: bool,
     pub subcommands: Vec<SubCommandInfo>,
     pub flags: Vec<ArgsInfo>,
-    pub custom_help: bool,
-    pub custom_parse: bool,
+    /// The fields with `#[flatten]`, whose options are merged
+    /// with the ones of the struct.
+    pub flattened: Vec<FlattenInfo>,
+    /// Dump the generated code, set with `#[cli(debug_expand)]`.
+    pub debug_expand: bool,
+    /// The `RenameAll` variant chosen with `#[cli(rename_all = "...")]`
+    pub rename_all: Option<&'static str>,
+    /// The prefix of the environment variables, set with
+    /// `#[cli(env_prefix = "...")]`.
+    pub env_prefix: Option<String>,
 }
 
 pub struct SubCommandInfo {
     pub name: TokenTree,
-    pub ty: TokenTree,
+    /// The type of the subcommand, without the `Option`.
+    pub ty: TyNode,
+    /// The field is an `Option<T>`, so the subcommand can be missing.
+    pub optional: bool,
+}
+
+pub struct FlattenInfo {
+    pub name: TokenTree,
+    /// The type of the field, that derives `Parser`.
+    pub ty: TyNode,
+}
+
+/// How many times an argument can be found on the command line,
+/// given by the type of the field.
+#[derive(PartialEq)]
+pub enum ArgKind {
+    /// `T`, the argument must be present.
+    Required,
+    /// `Option<T>`, the field is `None` when the argument is missing.
+    Optional,
+    /// `Vec<T>`, each occurrence of the argument is pushed.
+    Multiple,
+    /// An integer with `#[arg(count)]`, each occurrence of the
+    /// argument increments it, like `-vvv`.
+    Count,
 }
 
 pub struct ArgsInfo {
-    pub ty: TokenTree,
-    pub long_name: String,
+    pub identifier: TokenTree,
+    /// The type of a single value, e.g. `String` for `Option<String>`.
+    pub value_ty: TyNode,
+    pub kind: ArgKind,
+    pub long_name: RenamedNames,
+    /// The char literal of the short name, set with `#[arg(short = 'v')]`
+    /// or with `#[arg(short)]` to use the first letter of the field.
     pub short_name: Option<TokenTree>,
+    /// The argument is a value without a name, set with
+    /// `#[arg(positional)]` or `#[arg(index = 1)]`.
+    pub positional: bool,
+    /// The position of the argument, starting from 1. When missing
+    /// the positional arguments are in the order of declaration.
+    pub index: Option<usize>,
+    /// The doc comment of the field.
+    pub doc: DocNode,
+    /// The environment variable read when the argument is missing.
+    pub env: Option<EnvName>,
+    /// The value used when the argument is missing from the command
+    /// line and from the environment.
+    pub default: Option<DefaultValue>,
+    /// The type of the value derives `ValueEnum`, set with
+    /// `#[arg(value_enum)]`, so the possible values are in the help.
+    pub value_enum: bool,
+    /// The switch accepts also an explicit value like `--verbose=false`,
+    /// set with `#[arg(bool_value)]`.
+    pub bool_value: bool,
+}
+
+/// The name of the environment variable of an argument.
+pub enum EnvName {
+    /// `#[arg(env)]`, the name of the field in upper case, that
+    /// gets the prefix of `#[cli(env_prefix = "...")]`.
+    Field(String),
+    /// `#[arg(env = "NAME")]`, the name is used as written.
+    Fixed(String),
+}
+
+/// The default value of an argument.
+pub enum DefaultValue {
+    /// `#[arg(default = "8080")]`, parsed like a value of the command line.
+    Literal(String),
+    /// `#[arg(default_expr = some_fn())]`, an expression with the type
+    /// of the value that implements `Display` to be shown in the help.
+    Expr(String),
+}
+
+impl SubCommandInfo {
+    pub fn from_field(field: &FieldNode) -> Self {
+        let inner = field.ty.inner_of("Option");
+        SubCommandInfo {
+            name: field.identifier.clone(),
+            optional: inner.is_some(),
+            ty: inner.unwrap_or(field.ty.clone()),
+        }
+    }
+
+    pub fn declaration(&self) -> String {
+        format!(
+            "let mut {}: {} = {};\n",
+            local_name(&self.name),
+            self.local_ty(),
+            self.local_init()
+        )
+    }
+
+    /// The type of the variable that stores the subcommand.
+    pub fn local_ty(&self) -> String {
+        format!("::core::option::Option<{}>", self.ty)
+    }
+
+    pub fn local_init(&self) -> String {
+        "::core::option::Option::None".to_owned()
+    }
+
+    /// Generate the statements that parse the subcommand when the
+    /// value is one of its names.
+    pub fn check_value(&self) -> String {
+        let ty = &self.ty;
+        let local = local_name(&self.name);
+        format!(
+            "if let ::core::option::Option::Some(val) = value.to_str().filter(|val| <{ty} as {RUNTIME}::SubCommand>::matches(parser, val)) {{
+                {local} = ::core::option::Option::Some(<{ty} as {RUNTIME}::SubCommand>::parse(parser, val)?);
+                continue;
+            }}\n"
+        )
+    }
+
+    /// Generate the expression that returns the value of the field,
+    /// with an error if the subcommand is required and missing.
+    pub fn field_value(&self) -> String {
+        let local = local_name(&self.name);
+        if self.optional {
+            return local;
+        }
+        let name = RenamedNames::from_ident(&self.name.to_string());
+        let name = name.default_name();
+        format!("{local}.ok_or_else(|| {RUNTIME}::Error::MissingSubCommand({name:?}.to_owned()))?")
+    }
+
+    /// Generate the expression that returns the `DisplayCommand`s
+    /// of the subcommands.
+    pub fn display_commands(&self) -> String {
+        format!(
+            "<{} as {RUNTIME}::SubCommand>::commands(rename_all)",
+            self.ty
+        )
+    }
+}
+
+impl FlattenInfo {
+    pub fn from_field(field: &FieldNode) -> Self {
+        FlattenInfo {
+            name: field.identifier.clone(),
+            ty: field.ty.clone(),
+        }
+    }
+
+    pub fn declaration(&self) -> String {
+        format!(
+            "let mut {}: {} = {};\n",
+            local_name(&self.name),
+            self.local_ty(),
+            self.local_init()
+        )
+    }
+
+    /// The type of the variable that stores the flags of the
+    /// flattened parser while the parser loop is running.
+    pub fn local_ty(&self) -> String {
+        format!("<{} as {RUNTIME}::FlattenArgs>::Flags", self.ty)
+    }
+
+    pub fn local_init(&self) -> String {
+        format!("<{} as {RUNTIME}::FlattenArgs>::flags()", self.ty)
+    }
+
+    /// Generate the statement that passes the `arg` to the flattened
+    /// parser, and runs `on_match` when it is one of its options.
+    pub fn delegate(&self, on_match: &str) -> String {
+        let ty = &self.ty;
+        let local = local_name(&self.name);
+        format!(
+            "if <{ty} as {RUNTIME}::FlattenArgs>::parse_flag(&mut {local}, parser, &arg)? {{
+                {on_match}
+            }}\n"
+        )
+    }
+
+    /// Generate the expression that builds the flattened struct
+    /// at the end of the parser loop.
+    pub fn field_value(&self) -> String {
+        format!(
+            "<{} as {RUNTIME}::FlattenArgs>::from_flags({}, parser)?",
+            self.ty,
+            local_name(&self.name)
+        )
+    }
+
+    pub fn display_args(&self) -> String {
+        format!(
+            "<{} as {RUNTIME}::FlattenArgs>::display_args(rename_all)",
+            self.ty
+        )
+    }
+
+    /// The `ArgNames` of the flattened parser.
+    pub fn arg_names(&self) -> String {
+        format!("<{} as {RUNTIME}::FlattenArgs>::ARG_NAMES", self.ty)
+    }
+}
+
+impl ArgsInfo {
+    /// Build the argument from the field, looking at the `#[arg(...)]`
+    /// attribute to override the names.
+    ///
+    /// ```ignore
+    /// #[arg(short = 'v', long = "verbose-mode")]
+    /// pub verbose: bool,
+    /// #[arg(short)]
+    /// pub name: String,
+    /// ```
+    pub fn from_field(field: &FieldNode) -> Result<Self, MacroError> {
+        let (kind, value_ty) = if let Some(inner) = field.ty.inner_of("Option") {
+            if inner.inner_of("Vec").is_some() {
+                return Err(build_error!(
+                    field.identifier.clone(),
+                    "`Option<Vec<T>>` is not supported, use `Vec<T>` that is empty when the argument is missing"
+                ));
+            }
+            (ArgKind::Optional, inner)
+        } else if let Some(inner) = field.ty.inner_of("Vec") {
+            (ArgKind::Multiple, inner)
+        } else {
+            (ArgKind::Required, field.ty.clone())
+        };
+        let mut info = ArgsInfo {
+            identifier: field.identifier.clone(),
+            value_ty,
+            kind,
+            long_name: RenamedNames::from_ident(&field.name()),
+            short_name: None,
+            positional: false,
+            index: None,
+            doc: field.doc(),
+            env: None,
+            default: None,
+            value_enum: false,
+            bool_value: false,
+        };
+        for arg in field.attr_args("arg")? {
+            match arg.key.to_string().as_str() {
+                "short" if arg.value.is_none() => {
+                    info.short_name = Some(first_letter(field, &arg.key)?)
+                }
+                "short" => info.short_name = Some(arg.char_value()?),
+                "env" if arg.value.is_none() => {
+                    info.env = Some(EnvName::Field(field.name().to_uppercase()))
+                }
+                "env" => info.env = Some(EnvName::Fixed(arg.str_value()?)),
+                "default" | "default_expr" if info.default.is_some() => {
+                    return Err(build_error!(arg.key, "the default value is already set"));
+                }
+                "default" => info.default = Some(DefaultValue::Literal(arg.str_value()?)),
+                "default_expr" => {
+                    let Some(ref tokens) = arg.value else {
+                        return Err(build_error!(arg.key, "expected `default_expr = <expr>`"));
+                    };
+                    let expr = tokens.iter().cloned().collect::<TokenStream>();
+                    info.default = Some(DefaultValue::Expr(expr.to_string()));
+                }
+                "long" => info.long_name = RenamedNames::fixed(&arg.str_value()?),
+                "positional" => info.positional = true,
+                "value_enum" => info.value_enum = true,
+                "bool_value" => info.bool_value = true,
+                "count" => {
+                    if info.kind != ArgKind::Required {
+                        return Err(build_error!(
+                            arg.key,
+                            "`count` expects an integer field, like `u8`"
+                        ));
+                    }
+                    info.kind = ArgKind::Count;
+                }
+                "index" => {
+                    let index = arg.usize_value()?;
+                    if index == 0 {
+                        return Err(build_error!(arg.key, "the index starts from 1"));
+                    }
+                    info.positional = true;
+                    info.index = Some(index);
+                }
+                _ => return Err(build_error!(arg.key, "unknown `arg` attribute")),
+            }
+        }
+        if info.positional && info.kind == ArgKind::Count {
+            return Err(build_error!(
+                field.identifier.clone(),
+                "a positional argument can not be counted"
+            ));
+        }
+        if info.bool_value && (info.positional || !info.is_switch()) {
+            return Err(build_error!(
+                field.identifier.clone(),
+                "`bool_value` expects a `bool` switch"
+            ));
+        }
+        if info.positional && info.short_name.is_some() {
+            return Err(build_error!(
+                field.identifier.clone(),
+                "a positional argument can not have a short name"
+            ));
+        }
+        Ok(info)
+    }
+
+    /// The short name without the quotes, e.g. `v` for `'v'`.
+    fn short_char(&self) -> Option<String> {
+        let short_name = self.short_name.as_ref()?.to_string();
+        Some(short_name.trim_matches('\'').to_owned())
+    }
+
+    /// Generate the match arms that run the `body` when the argument
+    /// is found inside the parser loop, like `Short('v') | Long("verbose")`.
+    ///
+    /// When the long name depends on the `rename_all` style, the name
+    /// is checked inside a guard, so the short name needs its own arm.
+    pub fn match_arms(&self, body: &str) -> String {
+        let mut arms = String::new();
+        if self.positional {
+            // positional arguments are matched as `Value`
+            return arms;
+        }
+        let long_name = self.long_name.default_name();
+        let mut long_pattern = format!("{RUNTIME}::Arg::Long({long_name:?})");
+        if !self.long_name.is_fixed() {
+            if let Some(ref short_name) = self.short_name {
+                arms += &format!("{RUNTIME}::Arg::Short({short_name}) => {{ {body} }}\n");
+            }
+            let long_name = self.long_name.name_expr();
+            long_pattern = format!("{RUNTIME}::Arg::Long(long_name) if long_name == {long_name}");
+        } else if let Some(ref short_name) = self.short_name {
+            long_pattern = format!("{RUNTIME}::Arg::Short({short_name}) | {long_pattern}");
+        }
+        arms += &format!("{long_pattern} => {{ {body} }}\n");
+        arms
+    }
+
+    /// A `bool` field is a switch, so it is true when it is
+    /// present on the command line.
+    pub fn is_switch(&self) -> bool {
+        matches!(self.kind, ArgKind::Required | ArgKind::Optional)
+            && self.value_ty.to_string() == "bool"
+    }
+
+    /// Generate the declaration of the variable that store the
+    /// argument while the parser loop is running.
+    pub fn declaration(&self) -> String {
+        format!(
+            "let mut {}: {} = {};\n",
+            local_name(&self.identifier),
+            self.local_ty(),
+            self.local_init()
+        )
+    }
+
+    /// The type of the variable that stores the argument.
+    pub fn local_ty(&self) -> String {
+        let value_ty = &self.value_ty;
+        match self.kind {
+            ArgKind::Multiple => format!("::std::vec::Vec<{value_ty}>"),
+            ArgKind::Count => format!("{value_ty}"),
+            _ => format!("::core::option::Option<{value_ty}>"),
+        }
+    }
+
+    pub fn local_init(&self) -> String {
+        match self.kind {
+            ArgKind::Multiple => "::std::vec::Vec::new()".to_owned(),
+            ArgKind::Count => "0".to_owned(),
+            _ => "::core::option::Option::None".to_owned(),
+        }
+    }
+
+    /// Generate the statements that parse the value and store it
+    /// inside the variable, the `trace` variable enables the runtime trace.
+    pub fn store_value(&self) -> String {
+        let field = &self.identifier;
+        let identifier = local_name(field);
+        let value_ty = &self.value_ty;
+        let store = match self.kind {
+            ArgKind::Count => format!("{identifier} = {identifier}.saturating_add(1);"),
+            ArgKind::Multiple => format!(
+                "let value: {value_ty} = {};
+                {identifier}.push(value);",
+                self.value_expr()
+            ),
+            _ => format!(
+                "let value: {value_ty} = {};
+                {identifier} = ::core::option::Option::Some(value);",
+                self.value_expr()
+            ),
+        };
+        format!(
+            "{store}
+            {RUNTIME}::log_trace(trace, ::core::format_args!(\"matched `{field}`\"));"
+        )
+    }
+
+    /// Generate the statements that read the argument from its environment
+    /// variable when it is not found on the command line, and then that
+    /// use the default value when it is still missing.
+    pub fn fallback(&self) -> String {
+        let field = &self.identifier;
+        let identifier = local_name(field);
+        let value_ty = &self.value_ty;
+        let (missing, store) = match self.kind {
+            ArgKind::Multiple => (
+                format!("{identifier}.is_empty()"),
+                format!("{identifier}.push(value);"),
+            ),
+            ArgKind::Count => (
+                format!("{identifier} == 0"),
+                format!("{identifier} = value;"),
+            ),
+            _ => (
+                format!("{identifier}.is_none()"),
+                format!("{identifier} = ::core::option::Option::Some(value);"),
+            ),
+        };
+        let mut fallback = String::new();
+        if let Some(ref env) = self.env {
+            let value_expr = match self.is_switch() {
+                true => format!("{RUNTIME}::parse_env_switch(&value)?"),
+                false => format!("{RUNTIME}::ValueExt::parse(&value)?"),
+            };
+            let env = match env {
+                EnvName::Field(name) => {
+                    format!("::std::format!(\"{{}}{{}}\", parser.env_prefix, {name:?})")
+                }
+                EnvName::Fixed(name) => format!("::std::string::String::from({name:?})"),
+            };
+            fallback += &format!(
+                "if {missing} {{
+                    let env = {env};
+                    if let ::core::option::Option::Some(value) = ::std::env::var_os(&env) {{
+                        let value: {value_ty} = {value_expr};
+                        {store}
+                        {RUNTIME}::log_trace(trace, ::core::format_args!(\"matched `{field}` from `{{env}}`\"));
+                    }}
+                }}\n"
+            );
+        }
+        let value_expr = match self.default {
+            Some(DefaultValue::Literal(ref value)) => {
+                format!("{RUNTIME}::ValueExt::parse(&::std::ffi::OsString::from({value:?}))?")
+            }
+            Some(DefaultValue::Expr(_)) => format!("{}()", self.default_closure_name()),
+            None => return fallback,
+        };
+        fallback += &format!(
+            "if {missing} {{
+                let value: {value_ty} = {value_expr};
+                {store}
+            }}\n"
+        );
+        fallback
+    }
+
+    /// Generate the closure that returns the value of `default_expr`.
+    ///
+    /// The closure is declared before the local variables of the parser,
+    /// so the expression refers to the items of the user module and to
+    /// `Self`, e.g. `default_expr = Self::default_port()`.
+    pub fn default_closure(&self) -> String {
+        let Some(DefaultValue::Expr(ref expr)) = self.default else {
+            return String::new();
+        };
+        format!(
+            "let {} = || -> {} {{ {expr} }};\n",
+            self.default_closure_name(),
+            self.value_ty
+        )
+    }
+
+    fn default_closure_name(&self) -> String {
+        let identifier = self.identifier.to_string();
+        format!("__lexopt_default_{}", identifier.trim_start_matches("r#"))
+    }
+
+    /// Generate the `DisplayArg` that describe the argument
+    /// inside the `command_map`.
+    pub fn display_arg(&self) -> String {
+        let long_name = self.long_name.select_expr();
+        let short_name = match self.short_name {
+            Some(ref short_name) => format!("{short_name}.to_string()"),
+            None => "::std::string::String::new()".to_owned(),
+        };
+        let optional = self.kind != ArgKind::Required || self.is_switch() || self.default.is_some();
+        let positional = self.positional;
+        let takes_value = !self.is_switch() && self.kind != ArgKind::Count;
+        let multiple = matches!(self.kind, ArgKind::Multiple | ArgKind::Count);
+        let (env, env_prefixed) = match self.env {
+            Some(EnvName::Field(ref name)) => (name.as_str(), true),
+            Some(EnvName::Fixed(ref name)) => (name.as_str(), false),
+            None => ("", false),
+        };
+        let default = match self.default {
+            Some(DefaultValue::Literal(ref value)) => format!("{value:?}.to_owned()"),
+            Some(DefaultValue::Expr(ref expr)) => format!(
+                "{{ let value: {} = {expr}; ::std::string::ToString::to_string(&value) }}",
+                self.value_ty
+            ),
+            None => "::std::string::String::new()".to_owned(),
+        };
+        let possible_values = match self.value_enum {
+            true => format!(
+                "<{} as {RUNTIME}::ValueEnum>::POSSIBLE_VALUES.iter().map(|value| value.to_string()).collect()",
+                self.value_ty
+            ),
+            false => "::std::vec::Vec::new()".to_owned(),
+        };
+        let DocNode {
+            ref short,
+            ref long,
+        } = self.doc;
+        format!(
+            "{RUNTIME}::DisplayArg {{
+                long_name: {long_name}.to_owned(),
+                short_name: {short_name},
+                optional: {optional},
+                description: {short:?}.to_owned(),
+                long_description: {long:?}.to_owned(),
+                positional: {positional},
+                takes_value: {takes_value},
+                multiple: {multiple},
+                env: {env:?}.to_owned(),
+                env_prefixed: {env_prefixed},
+                default: {default},
+                possible_values: {possible_values},
+            }}"
+        )
+    }
+
+    /// Generate the expression that convert the variable to
+    /// the type of the field at the end of the parser loop.
+    ///
+    /// A missing switch is false, while the other required
+    /// arguments return an error.
+    pub fn field_value(&self) -> String {
+        let identifier = local_name(&self.identifier);
+        match self.kind {
+            ArgKind::Required if self.positional => {
+                let name = self.long_name.name_expr();
+                format!(
+                    "{identifier}.ok_or_else(|| {RUNTIME}::Error::MissingArgument(::std::format!(\"<{{}}>\", {name})))?"
+                )
+            }
+            ArgKind::Required if self.is_switch() => format!("{identifier}.unwrap_or_default()"),
+            ArgKind::Required => {
+                let long_name = self.long_name.name_expr();
+                format!(
+                    "{identifier}.ok_or_else(|| {RUNTIME}::Error::MissingArgument(::std::format!(\"--{{}}\", {long_name})))?"
+                )
+            }
+            ArgKind::Optional | ArgKind::Multiple | ArgKind::Count => identifier,
+        }
+    }
+
+    /// Generate the expression that parse the value of the argument
+    /// after that the parser matched it.
+    ///
+    /// A switch never consumes the next argument, and with `bool_value`
+    /// it accepts an explicit value when it is attached like
+    /// `--verbose=false`. The short form never takes a value, so the
+    /// switches can be clustered like `-vq`, while the values are
+    /// attached like `-nfoo` or `-n=foo`.
+    pub fn value_expr(&self) -> String {
+        if self.positional {
+            return format!("{RUNTIME}::ValueExt::parse(&value)?");
+        }
+        if self.is_switch() && !self.bool_value {
+            return "true".to_owned();
+        }
+        if self.bool_value {
+            return format!(
+                "match ::core::matches!(arg, {RUNTIME}::Arg::Long(_)).then(|| parser.optional_value()).flatten() {{
+                    ::core::option::Option::Some(value) => {RUNTIME}::ValueExt::parse(&value)?,
+                    ::core::option::Option::None => true,
+                }}"
+            );
+        }
+        format!("{RUNTIME}::ValueExt::parse(&parser.value()?)?")
+    }
+}
+
+/// The name of the variable that stores a field while the arguments are
+/// parsed, the prefix keeps it apart from the variables of the generated
+/// code like `parser` or `value`, so any field name can be used.
+pub fn local_name(field: &TokenTree) -> String {
+    let field = field.to_string();
+    format!("__lexopt_{}", field.trim_start_matches("r#"))
+}
+
+/// Build the short name of `#[arg(short)]` from the first letter
+/// of the field, the literal points to the `short` key.
+fn first_letter(field: &FieldNode, key: &TokenTree) -> Result<TokenTree, MacroError> {
+    let Some(letter) = field
+        .name()
+        .chars()
+        .next()
+        .filter(char::is_ascii_alphabetic)
+    else {
+        return Err(build_error!(
+            key.clone(),
+            "the field does not start with a letter, use `short = '...'`"
+        ));
+    };
+    let mut literal = Literal::character(letter);
+    literal.set_span(key.span());
+    Ok(TokenTree::Literal(literal))
+}
+
+/// Check that each short name is used by a single argument of the command.
+pub fn check_short_names(flags: &[ArgsInfo]) -> Result<(), MacroError> {
+    for (idx, flag) in flags.iter().enumerate() {
+        let Some(short_name) = flag.short_char() else {
+            continue;
+        };
+        if let Some(other) = flags[..idx]
+            .iter()
+            .find(|other| other.short_char().as_ref() == Some(&short_name))
+        {
+            return Err(build_error!(
+                flag.short_name.clone().unwrap(),
+                &format!(
+                    "the short name `-{short_name}` is already used by `{}`",
+                    other.identifier
+                )
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Sort the positional arguments at the end of the `flags` in the order
+/// that they have on the command line, and check that the order makes
+/// sense.
+pub fn sort_positionals(flags: &mut Vec<ArgsInfo>) -> Result<(), MacroError> {
+    let (positionals, others): (Vec<_>, Vec<_>) = flags.drain(..).partition(|flag| flag.positional);
+    let count = positionals.len();
+    // the arguments with an explicit index take their position, while
+    // the others fill the free positions in the order of declaration.
+    let mut sorted: Vec<(usize, ArgsInfo)> = vec![];
+    let mut implicit = vec![];
+    for positional in positionals {
+        let Some(index) = positional.index else {
+            implicit.push(positional);
+            continue;
+        };
+        if index > count {
+            return Err(build_error!(
+                positional.identifier,
+                &format!("the index {index} leaves a gap, the indices must be contiguous from 1")
+            ));
+        }
+        if sorted.iter().any(|(other, _)| *other == index - 1) {
+            return Err(build_error!(
+                positional.identifier,
+                "two positional arguments with the same index"
+            ));
+        }
+        sorted.push((index - 1, positional));
+    }
+    let mut key = 0;
+    for positional in implicit {
+        while sorted.iter().any(|(other, _)| *other == key) {
+            key += 1;
+        }
+        sorted.push((key, positional));
+    }
+    sorted.sort_by_key(|(key, _)| *key);
+    let positionals: Vec<ArgsInfo> = sorted.into_iter().map(|(_, flag)| flag).collect();
+
+    let mut optional: Option<&ArgsInfo> = None;
+    for (idx, positional) in positionals.iter().enumerate() {
+        if positional.kind == ArgKind::Multiple && idx != positionals.len() - 1 {
+            return Err(build_error!(
+                positional.identifier.clone(),
+                "only the last positional argument can be a `Vec`"
+            ));
+        }
+        if positional.kind == ArgKind::Required && optional.is_some() {
+            return Err(build_error!(
+                positional.identifier.clone(),
+                "a required positional argument can not follow an optional one"
+            ));
+        }
+        if positional.kind != ArgKind::Required {
+            optional = Some(positional);
+        }
+    }
+    flags.extend(others);
+    flags.extend(positionals);
+    Ok(())
+}
+
+/// Generate the statements that store a `Value` inside the positional
+/// arguments, the values that are not expected return an error.
+///
+/// The `positional_index` variable keeps the count of the values
+/// that are already stored.
+pub fn positional_match(flags: &[ArgsInfo]) -> String {
+    let mut arms = String::new();
+    for (index, positional) in flags.iter().filter(|flag| flag.positional).enumerate() {
+        let store_value = positional.store_value();
+        let pattern = match positional.kind {
+            // a `Vec` is always the last, and it takes all the remaining values.
+            ArgKind::Multiple => format!("_ if positional_index >= {index}"),
+            _ => format!("{index}"),
+        };
+        arms += &format!("{pattern} => {{ {store_value} }}\n");
+    }
+    if arms.is_empty() {
+        return unexpected_value();
+    }
+    format!(
+        "match positional_index {{
+            {arms}
+            _ => {{ {} }}
+        }}
+        positional_index += 1;",
+        unexpected_value()
+    )
+}
+
+/// Generate the statement that returns the error of an unexpected `value`.
+fn unexpected_value() -> String {
+    format!("return ::core::result::Result::Err({RUNTIME}::Arg::Value(value).unexpected().into());")
+}
+
+/// Generate the last arm of the parser loop, that passes the argument
+/// to the flattened parsers and returns an error when no one matches it.
+///
+/// The argument is detached from the parser, so the flattened
+/// parsers can read its value.
+pub fn unmatched_arm(flattened: &[FlattenInfo]) -> String {
+    if flattened.is_empty() {
+        return "_ => return ::core::result::Result::Err(arg.clone().unexpected().into()),\n"
+            .to_owned();
+    }
+    let delegations = flattened
+        .iter()
+        .map(|flatten| flatten.delegate("continue;"))
+        .collect::<String>();
+    format!(
+        "_ => {{
+            let mut long_buffer = ::std::string::String::new();
+            let arg = {RUNTIME}::detach_arg(arg, &mut long_buffer);
+            {delegations}
+            return ::core::result::Result::Err(arg.unexpected().into());
+        }}\n"
+    )
+}
+
+/// Generate the `ArgNames` of the options and of the flattened parsers,
+/// `values` is true when there are positional arguments or subcommands.
+pub fn arg_names(flags: &[ArgsInfo], values: bool, flattened: &[FlattenInfo]) -> String {
+    let options = flags.iter().filter(|flag| !flag.positional);
+    let long = options
+        .clone()
+        .map(|flag| format!("{:?},", flag.long_name.default_name()))
+        .collect::<String>();
+    let short = options
+        .filter_map(|flag| Some(format!("{},", flag.short_name.as_ref()?)))
+        .collect::<String>();
+    let values = values || flags.iter().any(|flag| flag.positional);
+    let flattened = flattened
+        .iter()
+        .map(|flatten| format!("{},", flatten.arg_names()))
+        .collect::<String>();
+    format!(
+        "{RUNTIME}::ArgNames {{
+            long: &[{long}],
+            short: &[{short}],
+            values: {values},
+            flattened: &[{flattened}],
+        }}"
+    )
+}
+
+/// Generate the const assertions that fail the build when the
+/// flattened parsers of `command` can not be merged with its options.
+pub fn check_flattened(command: &str, arg_names: &str) -> String {
+    format!(
+        "const _: () = {{
+            let names = {arg_names};
+            ::core::assert!(!names.collides(), \"`{command}` has two options with the same name, check the `#[flatten]` fields\");
+            ::core::assert!(!names.flattens_values(), \"the `#[flatten]` fields of `{command}` can not have positional arguments or subcommands\");
+        }};\n"
+    )
+}
+
+/// Generate the expression that returns all the `DisplayArg`s, the
+/// ones of the flattened parsers are between the arguments of the
+/// command and the builtin ones.
+pub fn display_args_expr(args: &str, flattened: &[FlattenInfo], builtins: &str) -> String {
+    if flattened.is_empty() {
+        return format!("::std::vec![{args}{builtins}]");
+    }
+    let flattened = flattened
+        .iter()
+        .map(|flatten| format!("{},", flatten.display_args()))
+        .collect::<String>();
+    format!("[::std::vec![{args}], {flattened} ::std::vec![{builtins}]].concat()")
+}
+
+/// Join the items inside a tuple, e.g. `(a, b,)`, or `()` when empty.
+fn tuple(items: &[String]) -> String {
+    let items = items
+        .iter()
+        .map(|item| format!("{item},"))
+        .collect::<String>();
+    format!("({items})")
+}
+
+/// Generate the match arm that returns the help of the current command,
+/// and the `DisplayArg` of `-h/--help`.
+pub fn help_arm(flags: &[ArgsInfo], flattened: &[FlattenInfo]) -> (String, String) {
+    let error = format!(
+        "{{
+            let long = ::core::matches!(arg, {RUNTIME}::Arg::Long(_));
+            {RUNTIME}::Error::DisplayHelp(parser.render_help(long))
+        }}"
+    );
+    builtin_arm(flags, flattened, 'h', "help", "Print help", &error)
+}
+
+/// Generate the match arm of `-V/--version` and its `DisplayArg`.
+pub fn version_arm(flags: &[ArgsInfo], flattened: &[FlattenInfo]) -> (String, String) {
+    let error = format!("{RUNTIME}::Error::DisplayVersion(parser.render_version())");
+    builtin_arm(flags, flattened, 'V', "version", "Print version", &error)
+}
+
+/// Generate the match arm of an argument that stops the parser with
+/// the `error`, so only `Parser::parse` prints the message and exits.
+/// The names that are already used by the fields are left to them,
+/// and the ones of the flattened parsers are checked with their
+/// `ArgNames`, because the fields are not known by the macro.
+fn builtin_arm(
+    flags: &[ArgsInfo],
+    flattened: &[FlattenInfo],
+    short_name: char,
+    long_name: &str,
+    description: &str,
+    error: &str,
+) -> (String, String) {
+    let short = !flags.iter().any(|flag| {
+        flag.short_name
+            .as_ref()
+            .is_some_and(|short| short.to_string() == format!("'{short_name}'"))
+    });
+    let long = !flags.iter().any(|flag| flag.long_name.contains(long_name));
+    if !short && !long {
+        return (String::new(), String::new());
+    }
+    let short_pattern = format!("{RUNTIME}::Arg::Short('{short_name}')");
+    let long_pattern = format!("{RUNTIME}::Arg::Long(\"{long_name}\")");
+    let (arm, short_value, long_value) = if flattened.is_empty() {
+        let pattern = match (short, long) {
+            (true, true) => format!("{short_pattern} | {long_pattern}"),
+            (true, false) => short_pattern,
+            _ => long_pattern,
+        };
+        (
+            format!("{pattern} => return ::core::result::Result::Err({error}),\n"),
+            if short {
+                format!("\"{short_name}\"")
+            } else {
+                "\"\"".to_owned()
+            },
+            if long {
+                format!("{long_name:?}")
+            } else {
+                "\"\"".to_owned()
+            },
+        )
+    } else {
+        // the flattened parsers see the argument only in the last arm,
+        // so the builtin arm is skipped when they use the same name
+        let used = |method: &str, name: String| {
+            let used = flattened
+                .iter()
+                .map(|flatten| format!("{}.{method}({name})", flatten.arg_names()))
+                .collect::<Vec<_>>()
+                .join(" || ");
+            format!("({used})")
+        };
+        let short_used = used("has_short", format!("'{short_name}'"));
+        let long_used = used("has_long", format!("{long_name:?}"));
+        let mut arm = String::new();
+        if short {
+            arm += &format!(
+                "{short_pattern} if !{short_used} => return ::core::result::Result::Err({error}),\n"
+            );
+        }
+        if long {
+            arm += &format!(
+                "{long_pattern} if !{long_used} => return ::core::result::Result::Err({error}),\n"
+            );
+        }
+        (
+            arm,
+            match short {
+                true => format!("if {short_used} {{ \"\" }} else {{ \"{short_name}\" }}"),
+                false => "\"\"".to_owned(),
+            },
+            match long {
+                true => format!("if {long_used} {{ \"\" }} else {{ {long_name:?} }}"),
+                false => "\"\"".to_owned(),
+            },
+        )
+    };
+    let display_arg = format!(
+        "{RUNTIME}::DisplayArg {{
+            long_name: {long_value}.to_owned(),
+            short_name: {short_value}.to_owned(),
+            optional: true,
+            description: \"{description}\".to_owned(),
+            long_description: ::std::string::String::new(),
+            positional: false,
+            takes_value: false,
+            multiple: false,
+            env: ::std::string::String::new(),
+            env_prefixed: false,
+            default: ::std::string::String::new(),
+            possible_values: ::std::vec::Vec::new(),
+        }},"
+    );
+    (arm, display_arg)
 }
 
 impl ParserMacroInfo {
-    pub fn to_tokens_stream(self) -> TokenStream {
+    pub fn into_tokens_stream(self) -> TokenStream {
         self.to_string().parse().unwrap()
     }
 }
 
-// FIXME: This needs to manage the commands info inside a symbol table
-// An example of this?
-//
-//       parser.command_map.insert(
-//            "@".to_owned(),
-//            DisplayCommand {
-//                name: "ex".to_owned(),
-//                subcommands: vec![DisplayCommand {
-//                    name: "install".to_owned(),
-//                    subcommands: vec![],
-//                    args: vec![DisplayArg {
-//                        long_name: "name".to_owned(),
-//                        short_name: "n".to_owned(),
-//                        optional: true,
-//                        description: String::new(),
-//                    }],
-//                    usage: "Random usage".to_owned(),
-//                    description: "random description".to_owned(),
-//                }],
-//                args: vec![DisplayArg {
-//                    description: "verbose".to_owned(),
-//                    optional: true,
-//                    long_name: "verbose".to_owned(),
-//                    short_name: "v".to_owned(),
-//                }],
-//                usage: "ex [command] [--[options]]".to_owned(),
-//                description: "a description".to_owned(),
-//            },
-//
-//        );
 impl std::fmt::Display for ParserMacroInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut code = r#"
-        use lexopt_helper::prelude::*;
-"#
-        .to_string();
-
         let struct_identifier = self.identifier.clone().unwrap();
-        code += &format!("\nimpl {struct_identifier} {{");
+        let mut code = String::new();
 
-        let mut new_params = String::new();
-        let mut self_assign = String::new();
-        let mut self_new_call = String::new();
+        let mut self_fields = String::new();
         let mut declarations = String::new();
         let mut while_match = String::new();
         let mut check_subcommand = String::new();
+        let mut display_args = String::new();
+        let mut fallbacks = String::new();
+        let mut default_closures = String::new();
+        let mut display_subcommands = String::new();
+        // the names, the types and the initial values of the variables
+        // that are inside the `Flags` tuple
+        let mut local_names = Vec::new();
+        let mut local_tys = Vec::new();
+        let mut local_inits = Vec::new();
         for subcommands in self.subcommands.iter() {
             let identifier = subcommands.name.clone();
-            let ty = subcommands.ty.clone();
-            declarations += &format!("let mut {identifier}: Option<{ty}> = None;\n");
-            new_params += &format!("{identifier}: {ty},");
-            self_assign += &format!("{identifier}: {identifier},");
-            // FIXME: there is a way to improve the unwrap or default?
-            self_new_call += &format!("{identifier}.unwrap(),");
-            check_subcommand += &format!(
-                "if {ty}::is_this_subcommad(val) {{
-                                    {identifier} = Some({ty}::parse(&mut parser, val)?);
-                                }}\n"
-            );
+            declarations += &subcommands.declaration();
+            local_names.push(local_name(&identifier));
+            local_tys.push(subcommands.local_ty());
+            local_inits.push(subcommands.local_init());
+            self_fields += &format!("{identifier}: {},", subcommands.field_value());
+            check_subcommand += &subcommands.check_value();
+            display_subcommands += &format!("{},", subcommands.display_commands());
         }
         for flag in self.flags.iter() {
-            let identifier = flag.long_name.clone();
-            let ty = flag.ty.clone();
-            declarations += &format!("let mut {identifier}: Option<{ty}> = None;\n");
-            new_params += &format!("{identifier}: {ty},");
-            self_assign += &format!("{identifier}: {identifier},");
-            self_new_call += &format!("{identifier}.unwrap_or_default(),");
-            while_match += &format!(
-                "Long(\"{identifier}\") => {{
-                    let value: {ty} = parser.value()?.parse()?;
-                    {identifier} = Some(value);
-                 }}\n"
+            let identifier = flag.identifier.clone();
+            declarations += &flag.declaration();
+            local_names.push(local_name(&identifier));
+            local_tys.push(flag.local_ty());
+            local_inits.push(flag.local_init());
+            self_fields += &format!("{identifier}: {},", flag.field_value());
+            while_match += &flag.match_arms(&flag.store_value());
+            fallbacks += &flag.fallback();
+            default_closures += &flag.default_closure();
+            display_args += &format!("{},", flag.display_arg());
+        }
+        for flatten in self.flattened.iter() {
+            let identifier = &flatten.name;
+            declarations += &flatten.declaration();
+            local_names.push(local_name(identifier));
+            local_tys.push(flatten.local_ty());
+            local_inits.push(flatten.local_init());
+            self_fields += &format!("{identifier}: {},", flatten.field_value());
+        }
+        // the arms of the options, without the builtin ones
+        let flag_arms = while_match.clone();
+
+        let mut builtin_args = String::new();
+        let (help_arm, help_arg) = help_arm(&self.flags, &self.flattened);
+        while_match += &help_arm;
+        builtin_args += &help_arg;
+        if self.version {
+            let (version_arm, version_arg) = version_arm(&self.flags, &self.flattened);
+            while_match += &version_arm;
+            builtin_args += &version_arg;
+        }
+
+        let optional_subcommand = self
+            .subcommands
+            .iter()
+            .all(|subcommand| subcommand.optional);
+        let display_subcommands = match display_subcommands.is_empty() {
+            true => "::std::vec::Vec::new()".to_owned(),
+            false => format!("[{display_subcommands}].concat()"),
+        };
+        let DocNode {
+            ref short,
+            ref long,
+        } = self.doc;
+        let (name, description, version, author) = match self.cli {
+            true => (
+                "Self::NAME".to_owned(),
+                format!("Self::ABOUT.unwrap_or({short:?})"),
+                "Self::VERSION.unwrap_or_default()",
+                "Self::AUTHOR.unwrap_or_default()",
+            ),
+            false => (
+                "::core::env!(\"CARGO_PKG_NAME\")".to_owned(),
+                format!("{short:?}"),
+                "\"\"",
+                "\"\"",
+            ),
+        };
+        // the builtin arguments do not depend on the style
+        let rename_all_param = match self.flags.is_empty() && self.flattened.is_empty() {
+            true => "_rename_all",
+            false => "rename_all",
+        };
+        if let Some(style) = self.rename_all {
+            code += &format!(
+                "const RENAME_ALL: {RUNTIME}::RenameAll = {RUNTIME}::RenameAll::{style};\n"
             );
-            // if the short name is specified, add it to the match
-            if let Some(ref short_name) = flag.short_name {
-                while_match = format!("Short(\"{short_name}\") | {while_match}");
-            }
         }
+        if let Some(ref prefix) = self.env_prefix {
+            code += &format!("const ENV_PREFIX: &'static str = {prefix:?};\n");
+        }
+        // the items used to merge the struct inside another parser
+        let arg_names = arg_names(&self.flags, !self.subcommands.is_empty(), &self.flattened);
+        let display_args = display_args_expr(&display_args, &self.flattened, "");
+        let mut flatten_code = format!(
+            "type Flags = {};
+            const ARG_NAMES: {RUNTIME}::ArgNames = {arg_names};
 
-        let new_params = new_params.strip_suffix(",").unwrap_or(&new_params);
+            fn display_args({rename_all_param}: {RUNTIME}::RenameAll) -> ::std::vec::Vec<{RUNTIME}::DisplayArg> {{
+                {display_args}
+            }}\n",
+            tuple(&local_tys)
+        );
         code += &format!(
-            "fn new({new_params}) -> Self {{
-                    Self{{ {self_assign} }}
+            "fn display_command(rename_all: {RUNTIME}::RenameAll) -> {RUNTIME}::DisplayCommand {{
+                {RUNTIME}::DisplayCommand {{
+                    name: {name}.to_owned(),
+                    subcommands: {display_subcommands},
+                    optional_subcommand: {optional_subcommand},
+                    args: [<Self as {RUNTIME}::FlattenArgs>::display_args(rename_all), ::std::vec![{builtin_args}]].concat(),
+                    usage: ::std::string::String::new(),
+                    description: {description}.to_owned(),
+                    long_description: {long:?}.to_owned(),
+                    version: {version}.to_owned(),
+                    author: {author}.to_owned(),
+                }}
             }}\n"
         );
 
-        let self_new_call = self_new_call.strip_suffix(",").unwrap_or(&self_new_call);
+        let mut locals = "let trace = parser.trace;\n".to_owned();
+        if self.flags.iter().any(|flag| !flag.long_name.is_fixed()) {
+            locals += "let rename_all = parser.rename_all;\n";
+        }
+        if self.flags.iter().any(|flag| flag.positional) {
+            declarations += "let mut positional_index = 0;\n";
+        }
+        let positional_match = positional_match(&self.flags);
+        let unmatched_arm = unmatched_arm(&self.flattened);
+
         code += &format!(
-            "fn parse() -> Result<Self, Error> {{
-                            let mut parser = ParserInfo::new();
+            "fn parse_args(parser: &mut {RUNTIME}::ParserInfo) -> ::core::result::Result<Self, {RUNTIME}::Error> {{
+                            {default_closures}
+                            {locals}
                             {declarations}
 
                             loop {{
-                                 let Some(ref arg) = parser.next()? else {{ break; }};
-                                 println!(\"{{:?}}\",arg);
+                                 let ::core::option::Option::Some(ref arg) = parser.next()? else {{ break; }};
+                                 {RUNTIME}::log_trace(trace, ::core::format_args!(\"token {{arg:?}}\"));
                                  match arg.clone() {{
                                       {while_match}
-                                      Value(value) => {{
-                                            let val = value.as_os_str().to_str().unwrap();
+                                      {RUNTIME}::Arg::Value(value) => {{
                                             {check_subcommand}
+                                            {positional_match}
                                       }}
-                                      _ => {{
-                                            return Err(arg.clone().unexpected())
-                                        }}
+                                      {unmatched_arm}
                                   }}
                             }}
+                            {fallbacks}
 
-                            println!(\"end parsing subcommand\");
-                            Ok(Self::new({self_new_call}))
-                          }}"
+                            ::core::result::Result::Ok(Self {{ {self_fields} }})
+                          }}\n"
         );
-        eprintln!("******* {code}");
-        code += "}"; // close the impl
-        writeln!(f, "{code}")
+
+        // the same loop and fallbacks split in functions, used when
+        // the struct is flattened inside another one.
+        let names = tuple(&local_names);
+        let pattern = local_names
+            .iter()
+            .map(|name| format!("mut {name}"))
+            .collect::<Vec<_>>();
+        let pattern = tuple(&pattern);
+        let matched = format!("return ::core::result::Result::Ok(({names}, true));");
+        let delegations = self
+            .flattened
+            .iter()
+            .map(|flatten| flatten.delegate(&matched))
+            .collect::<String>();
+        // an empty body returns the unit type without lints
+        let flags = match local_inits.is_empty() {
+            true => String::new(),
+            false => tuple(&local_inits),
+        };
+        flatten_code += &format!(
+            "fn flags() -> <Self as {RUNTIME}::FlattenArgs>::Flags {{
+                {flags}
+            }}
+
+            #[allow(unused_mut, unused_variables, unreachable_code)]
+            fn match_flag({pattern}: <Self as {RUNTIME}::FlattenArgs>::Flags, parser: &mut {RUNTIME}::ParserInfo, arg: &{RUNTIME}::Arg<'_>) -> ::core::result::Result<(<Self as {RUNTIME}::FlattenArgs>::Flags, bool), {RUNTIME}::Error> {{
+                let trace = parser.trace;
+                let rename_all = parser.rename_all;
+                match arg.clone() {{
+                    {flag_arms}
+                    _ => {{
+                        {delegations}
+                        return ::core::result::Result::Ok(({names}, false));
+                    }}
+                }}
+                ::core::result::Result::Ok(({names}, true))
+            }}
+
+            #[allow(unused_mut, unused_variables)]
+            fn from_flags(flags: <Self as {RUNTIME}::FlattenArgs>::Flags, parser: &mut {RUNTIME}::ParserInfo) -> ::core::result::Result<Self, {RUNTIME}::Error> {{
+                {default_closures}
+                let {pattern} = flags;
+                let trace = parser.trace;
+                let rename_all = parser.rename_all;
+                {fallbacks}
+                ::core::result::Result::Ok(Self {{ {self_fields} }})
+            }}"
+        );
+        writeln!(
+            f,
+            "impl {RUNTIME}::Parser for {struct_identifier} {{ {code} }}
+            impl {RUNTIME}::FlattenArgs for {struct_identifier} {{ {flatten_code} }}"
+        )?;
+        if !self.flattened.is_empty() {
+            let arg_names = format!("<{struct_identifier} as {RUNTIME}::FlattenArgs>::ARG_NAMES");
+            writeln!(
+                f,
+                "{}",
+                check_flattened(&struct_identifier.to_string(), &arg_names)
+            )?;
+        }
+        Ok(())
     }
 }
 
 /// EXPAND: Procedural macros expands result in the Parser implementation
 /// with all the meta information that the parser needs a runtime.
 ///
-/// ```norun
-/// use lexopt_helper::prelude::*;
+/// The generated code uses only absolute paths, so it does not
+/// depend on the imports of the module where the struct is defined.
 ///
+/// ```norun
 /// struct NameOfYourParser {
 ///     pub verbose: bool,
 /// }
 ///
 /// // This is generated by the `Parser` derive macros
-/// impl NameOfYourParser {
-///     fn new(verbose: bool) -> Self {
-///         Self{ verbose: verbose }
+/// impl ::lexopt_cli::prelude::Parser for NameOfYourParser {
+///     fn display_command(rename_all: RenameAll) -> DisplayCommand {
+///         // the metadata used to print the help
 ///     }
 ///
-///     fn parse() -> Result<Self, Error> {
-///         let mut parser = ParserInfo::new();
+///     fn parse_args(parser: &mut ParserInfo) -> Result<Self, Error> {
 ///         let mut verbose: Option<bool> = None;
 ///
 ///         while let Some(arg) parser.next()? {
@@ -175,65 +1224,93 @@ impl std::fmt::Display for ParserMacroInfo {
 ///                 _ => return Err(arg.unexpected()),
 ///             }
 ///         }
-///         Ok(Self::new(verbose))
+///         Ok(Self { verbose: verbose.unwrap_or_default() })
 ///     }
 /// }
+///
+/// // the same loop split in `match_flag` and `from_flags`, used
+/// // when the struct is a `#[flatten]` field of another parser
+/// impl ::lexopt_cli::prelude::FlattenArgs for NameOfYourParser {
+///     type Flags = (Option<bool>,);
+///     // ...
+/// }
 /// ````
 pub fn parse(stream: TokenStream) -> TokenStream {
-    let parser = build_parser!(TRACER);
-    let ast = parser.parse(&stream).unwrap();
-    let Ok(parser_impl) = generate_parser(ast, &TRACER).map_err(|err| err.emit()) else {
-        unimplemented!()
+    let parser_impl = match parse_item(&stream).and_then(|ast| generate_parser(ast, &TRACER)) {
+        Ok(parser_impl) => parser_impl,
+        Err(err) => return err.to_compile_error(),
     };
-    parser_impl.to_tokens_stream()
+    if parser_impl.debug_expand || expand::is_enabled() {
+        let identifier = parser_impl.identifier.clone().unwrap().to_string();
+        expand::dump(&identifier, &parser_impl.to_string());
+    }
+    parser_impl.into_tokens_stream()
 }
 
 pub fn generate_parser<T: KParserTracer>(
-    ast: TopLevelNode,
+    ast: ItemNode,
     tracer: &T,
-) -> Result<ParserMacroInfo, KParserError> {
+) -> Result<ParserMacroInfo, MacroError> {
     let mut info = ParserMacroInfo {
         identifier: None,
+        doc: DocNode::default(),
+        cli: false,
+        version: false,
         subcommands: vec![],
         flags: vec![],
-        custom_help: false,
-        custom_parse: false,
+        flattened: vec![],
+        debug_expand: false,
+        rename_all: None,
+        env_prefix: None,
     };
     match ast {
-        TopLevelNode::Struct(ast) => {
-            info.identifier = Some(ast.name);
+        ItemNode::Struct(ast) => {
+            info.doc = ast.doc();
+            // `#[cli_meta]` is the copy made by `cli` when it is
+            // expanded before the derive macro
+            let has_attr = |name: &str| ast.attrs.iter().any(|attr| attr.name.to_string() == name);
+            let cli_attr = match has_attr("cli_meta") {
+                true => "cli_meta",
+                false => "cli",
+            };
+            info.cli = has_attr(cli_attr);
+            for arg in ast.attr_args(cli_attr)? {
+                match arg.key.to_string().as_str() {
+                    "version" => info.version = true,
+                    "debug_expand" => info.debug_expand = true,
+                    "env_prefix" => info.env_prefix = Some(arg.str_value()?),
+                    _ => {}
+                }
+                if arg.key.to_string() != "rename_all" {
+                    // the other values are managed by the `cli` macro
+                    continue;
+                }
+                let style = rename_all_variant(&arg.str_value()?).ok_or(build_error!(
+                    arg.key,
+                    "rename_all must be one of `kebab-case`, `snake_case` or `verbatim`"
+                ))?;
+                info.rename_all = Some(style);
+            }
+            info.identifier = Some(ast.identifier);
             for field in ast.fields {
                 trace!(tracer, "{field}");
-                if field.attrs.contains_key("subcommand") {
-                    info.subcommands.push(SubCommandInfo {
-                        name: field.identifier,
-                        // FIXME: the type is more complex we are missing
-                        // the generics.
-                        ty: field.ty.identifier,
-                    });
+                if field.has_attr("subcommand") {
+                    info.subcommands.push(SubCommandInfo::from_field(&field));
+                } else if field.has_attr("flatten") {
+                    info.flattened.push(FlattenInfo::from_field(&field));
                 } else {
-                    // FIXME: we should be able to rename the fields
-                    info.flags.push(ArgsInfo {
-                        // FIXME: the ty is more complex, we are missing the
-                        // generics
-                        ty: field.ty.identifier,
-                        // FIXME: this should store the information as TokenTree
-                        long_name: field.identifier.to_string(),
-                        short_name: None,
-                    });
+                    info.flags.push(ArgsInfo::from_field(&field)?);
                 }
             }
+            sort_positionals(&mut info.flags)?;
+            check_short_names(&info.flags)?;
         }
-        TopLevelNode::Enum(ast) => {
-            info.identifier = Some(ast.identifier.clone());
-            for value in ast.values {
-                info.subcommands.push(SubCommandInfo {
-                    name: value.identifier,
-                    ty: ast.identifier.clone(),
-                })
-            }
+        ItemNode::Enum(ast) => {
+            return Err(build_error!(
+                ast.identifier,
+                "Parser can be derived only on structs, use SubCommand for enums"
+            ))
         }
-        _ => unimplemented!(),
     }
     Ok(info)
 }
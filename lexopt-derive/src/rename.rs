@@ -0,0 +1,111 @@
+//! Naming style of the arguments and of the subcommands.
+//!
+//! The style is chosen at runtime with `#[cli(rename_all = "...")]`
+//! on the top level parser, so each name is generated for all the
+//! styles and the parser select the right one.
+
+/// The names of a rust identifier for each style, in the same order
+/// of `RenameAll` inside `lexopt_helper`.
+pub struct RenamedNames {
+    names: [String; 3],
+}
+
+impl RenamedNames {
+    /// Build the names from a rust identifier like `dry_run`
+    /// or `InstallPkg`.
+    pub fn from_ident(ident: &str) -> Self {
+        let snake_case = to_snake_case(ident);
+        RenamedNames {
+            names: [snake_case.replace('_', "-"), snake_case, ident.to_owned()],
+        }
+    }
+
+    /// A name chosen by the user, that is the same for all the styles.
+    pub fn fixed(name: &str) -> Self {
+        RenamedNames {
+            names: [name.to_owned(), name.to_owned(), name.to_owned()],
+        }
+    }
+
+    pub fn is_fixed(&self) -> bool {
+        self.names.iter().all(|name| *name == self.names[0])
+    }
+
+    /// Return true if the name is used with one of the styles.
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.iter().any(|other| other == name)
+    }
+
+    /// The kebab-case name, that is the default one.
+    pub fn default_name(&self) -> &str {
+        &self.names[0]
+    }
+
+    /// Generate the expression that returns the name, the `rename_all`
+    /// variable is used only when the name is not fixed.
+    pub fn name_expr(&self) -> String {
+        match self.is_fixed() {
+            true => format!("{:?}", self.names[0]),
+            false => self.select_expr(),
+        }
+    }
+
+    /// Generate the expression that returns the name with the
+    /// style stored inside the `rename_all` variable.
+    pub fn select_expr(&self) -> String {
+        let [kebab_case, snake_case, verbatim] = &self.names;
+        format!("rename_all.select([{kebab_case:?}, {snake_case:?}, {verbatim:?}])")
+    }
+}
+
+/// Parse the value of `rename_all` and return the `RenameAll`
+/// variant of `lexopt_helper`.
+pub fn rename_all_variant(style: &str) -> Option<&'static str> {
+    match style {
+        "kebab-case" => Some("KebabCase"),
+        "snake_case" => Some("SnakeCase"),
+        "verbatim" => Some("Verbatim"),
+        _ => None,
+    }
+}
+
+/// Convert an identifier to snake_case, the acronyms are kept
+/// together so `HTTPServer` became `http_server`.
+pub fn to_snake_case(ident: &str) -> String {
+    let chars: Vec<char> = ident.chars().collect();
+    let mut result = String::new();
+    for (idx, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && idx > 0 {
+            let prev = chars[idx - 1];
+            let next_is_lower = chars.get(idx + 1).is_some_and(|next| next.is_lowercase());
+            if prev.is_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_uppercase() && next_is_lower)
+            {
+                result.push('_');
+            }
+        }
+        result.extend(c.to_lowercase());
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_snake_case;
+
+    #[test]
+    fn snake_case_of_camel_case() {
+        assert_eq!(to_snake_case("Install"), "install");
+        assert_eq!(to_snake_case("DryRun"), "dry_run");
+        assert_eq!(to_snake_case("dryRun"), "dry_run");
+        assert_eq!(to_snake_case("dry_run"), "dry_run");
+    }
+
+    #[test]
+    fn snake_case_keeps_acronyms_together() {
+        assert_eq!(to_snake_case("HTTPServer"), "http_server");
+        assert_eq!(to_snake_case("ParseURL"), "parse_url");
+        assert_eq!(to_snake_case("Ipv4Addr"), "ipv4_addr");
+    }
+}
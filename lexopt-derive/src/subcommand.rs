@@ -1,14 +1,18 @@
 //! Sub Command derive macro implementation
 use std::fmt;
 
-use kproc_parser::kparser::{KParserError, KParserTracer};
+use kproc_parser::kparser::KParserTracer;
 use kproc_parser::proc_macro::{TokenStream, TokenTree};
-use kproc_parser::rust::ast_nodes::TopLevelNode;
-use kproc_parser::rust::kenum::EnumValueKind;
 
-use crate::macros::build_parser;
-use crate::parser::{ArgsInfo, SubCommandInfo};
-use crate::TRACER;
+use crate::ast::{parse_item, DocNode, ItemNode, TyNode, VariantKind};
+use crate::error::MacroError;
+use crate::expand;
+use crate::parser::{
+    arg_names, check_flattened, check_short_names, display_args_expr, help_arm, positional_match,
+    sort_positionals, unmatched_arm, ArgsInfo, FlattenInfo, SubCommandInfo,
+};
+use crate::rename::RenamedNames;
+use crate::{RUNTIME, TRACER};
 
 struct SubCommandMacroInfo {
     pub identifier: Option<TokenTree>,
@@ -17,188 +21,312 @@ struct SubCommandMacroInfo {
 
 struct MacroInfo {
     pub identifier: TokenTree,
+    /// The name of the subcommand on the command line.
+    pub name: RenamedNames,
+    /// The doc comment of the variant.
+    pub doc: DocNode,
     /// All the fields that are defined inside
     /// the subcommand.
     pub fields: Vec<ArgsInfo>,
     /// All the sub commands that a subcommand will
     /// implement
     pub subcommands: Vec<SubCommandInfo>,
+    /// The fields with `#[flatten]`, whose options are merged
+    /// with the ones of the subcommand.
+    pub flattened: Vec<FlattenInfo>,
+    /// The struct that derives `Parser` wrapped by a tuple
+    /// variant, like `Install(InstallArgs)`.
+    pub wrapped: Option<TyNode>,
+}
+
+impl MacroInfo {
+    /// Generate the `DisplayCommand` of a tuple variant, that is the
+    /// command of the wrapped struct with the name of the variant.
+    ///
+    /// The doc comment of the variant, when present, replaces the one
+    /// of the struct.
+    fn wrapped_command(&self) -> String {
+        let ty = self.wrapped.as_ref().unwrap();
+        let name = self.name.select_expr();
+        let mut description = String::new();
+        let DocNode {
+            ref short,
+            ref long,
+        } = self.doc;
+        if !short.is_empty() {
+            description = format!(
+                "command.description = {short:?}.to_owned();
+                command.long_description = {long:?}.to_owned();"
+            );
+        }
+        format!(
+            "{{
+                let mut command = <{ty} as {RUNTIME}::Parser>::display_command(rename_all);
+                command.name = {name}.to_owned();
+                command.version = ::std::string::String::new();
+                command.author = ::std::string::String::new();
+                {description}
+                command
+            }},"
+        )
+    }
 }
 
-// FIXME: we need to register the command inside the symbol table
-//
-//        parser.command_map.insert(
-//            "install".to_owned(),
-//            DisplayCommand {
-//                name: "install".to_owned(),
-//                subcommands: vec![],
-//                args: vec![DisplayArg {
-//                    long_name: "name".to_owned(),
-//                    short_name: "n".to_owned(),
-//                    optional: true,
-//                    description: String::new(),
-//                }],
-//                usage: "Random usage".to_owned(),
-//                description: "random description".to_owned(),
-//            },
-//        );
 impl fmt::Display for SubCommandMacroInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let idetifier = self.identifier.clone().unwrap();
         let mut match_body = String::new();
-        let mut subcommands_fn = String::new();
         let mut subcommands_names = String::new();
+        let mut display_commands = String::new();
+        let mut checks = String::new();
         for subcommand in self.subcommand.iter() {
             let subcommand_name = subcommand.identifier.to_string();
-            let identifier = subcommand.identifier.to_string().to_lowercase();
-            match_body += &format!("\"{identifier}\" => Self::parse_{identifier}(parser),\n");
-
+            let name = subcommand.name.select_expr();
+            subcommands_names += &format!("{name},");
+            if let Some(ref ty) = subcommand.wrapped {
+                match_body += &format!(
+                    "cmd if cmd == {name} => ::core::result::Result::Ok(Self::{subcommand_name}(<{ty} as {RUNTIME}::Parser>::parse_args(parser)?)),\n"
+                );
+                display_commands += &subcommand.wrapped_command();
+                continue;
+            }
             let mut while_match = String::new();
             let mut declarations = String::new();
-            let mut new_params = String::new();
-            let mut self_assign = String::new();
-            let mut self_new_call = String::new();
-            // FIXME: Parse the subcommands
+            let mut self_fields = String::new();
+            let mut display_args = String::new();
+            let mut fallbacks = String::new();
+            let mut default_closures = String::new();
+            let mut check_subcommand = String::new();
+            let mut display_subcommands = String::new();
+            for subcommands in subcommand.subcommands.iter() {
+                let identifier = &subcommands.name;
+                declarations += &subcommands.declaration();
+                self_fields += &format!("{identifier}: {},", subcommands.field_value());
+                check_subcommand += &subcommands.check_value();
+                display_subcommands += &format!("{},", subcommands.display_commands());
+            }
+            let optional_subcommand = subcommand
+                .subcommands
+                .iter()
+                .all(|subcommand| subcommand.optional);
+            let display_subcommands = match display_subcommands.is_empty() {
+                true => "::std::vec::Vec::new()".to_owned(),
+                false => format!("[{display_subcommands}].concat()"),
+            };
             for flag in subcommand.fields.iter() {
-                let identifier = flag.long_name.clone();
-                let ty = flag.ty.clone();
-                declarations += &format!("let mut {identifier}: Option<{ty}> = None;\n");
-                new_params += &format!("{identifier}: {ty},");
-                self_assign += &format!("{identifier}: {identifier},");
-                self_new_call += &format!("{identifier}: {identifier}.unwrap_or_default(),");
+                let identifier = flag.identifier.clone();
+                declarations += &flag.declaration();
+                self_fields += &format!("{identifier}: {},", flag.field_value());
+                while_match += &flag.match_arms(&flag.store_value());
+                fallbacks += &flag.fallback();
+                default_closures += &flag.default_closure();
+                display_args += &format!("{},", flag.display_arg());
+            }
+            for flatten in subcommand.flattened.iter() {
+                declarations += &flatten.declaration();
+                self_fields += &format!("{}: {},", flatten.name, flatten.field_value());
+            }
+            if !subcommand.flattened.is_empty() {
+                let arg_names = arg_names(
+                    &subcommand.fields,
+                    !subcommand.subcommands.is_empty(),
+                    &subcommand.flattened,
+                );
+                let command = format!("{idetifier}::{subcommand_name}");
+                checks += &check_flattened(&command, &arg_names);
+            }
+            let (help_arm, help_arg) = help_arm(&subcommand.fields, &subcommand.flattened);
+            while_match += &help_arm;
+            let display_args = display_args_expr(&display_args, &subcommand.flattened, &help_arg);
+            let DocNode {
+                ref short,
+                ref long,
+            } = subcommand.doc;
+            display_commands += &format!(
+                "{RUNTIME}::DisplayCommand {{
+                    name: {name}.to_owned(),
+                    subcommands: {display_subcommands},
+                    optional_subcommand: {optional_subcommand},
+                    args: {display_args},
+                    usage: ::std::string::String::new(),
+                    description: {short:?}.to_owned(),
+                    long_description: {long:?}.to_owned(),
+                    version: ::std::string::String::new(),
+                    author: ::std::string::String::new(),
+                }},"
+            );
+            if subcommand
+                .fields
+                .iter()
+                .any(|flag| !flag.long_name.is_fixed())
+            {
+                declarations += "let rename_all = parser.rename_all;\n";
+            }
+            let positional = subcommand.fields.iter().any(|flag| flag.positional);
+            if positional {
+                declarations += "let mut positional_index = 0;\n";
+            }
+            if positional || !check_subcommand.is_empty() {
+                let positional_match = positional_match(&subcommand.fields);
                 while_match += &format!(
-                    "Long(\"{identifier}\") => {{
-                        println!(\"match in the subcommand\");
-                        let value: {ty} = parser.value()?.parse()?;
-                        {identifier} = Some(value);
-                    }}\n"
+                    "{RUNTIME}::Arg::Value(value) => {{ {check_subcommand} {positional_match} }}\n"
                 );
-                // if the short name is specified, add it to the match
-                if let Some(ref short_name) = flag.short_name {
-                    while_match = format!("Short(\"{short_name}\") | {while_match}");
-                }
             }
+            let unmatched_arm = unmatched_arm(&subcommand.flattened);
 
-            // TODO: this needs to be move in another function
-            subcommands_fn += &format!("pub fn parse_{identifier}(parser: &mut ParserInfo) -> Result<Self, Error> {{
+            // the variant is parsed inside the arm of `SubCommand::parse`,
+            // so the enum does not get inherent items and `Self` is the enum
+            match_body += &format!("cmd if cmd == {name} => {{
+                                                {default_closures}
+                                                let trace = parser.trace;
                                                 {declarations}
                                                 loop {{
-                                                    let Some(ref arg) = parser.next()? else {{ break; }};
-                                                    println!(\"{{:?}}\", arg);
+                                                    let ::core::option::Option::Some(ref arg) = parser.next()? else {{ break; }};
+                                                    {RUNTIME}::log_trace(trace, ::core::format_args!(\"token {{arg:?}}\"));
                                                     match arg.clone() {{
                                                         {while_match}
-                                                        _ => return Err(arg.clone().unexpected()),
+                                                        {unmatched_arm}
                                                     }}
                                                 }}
+                                                {fallbacks}
 
-                                    println!(\"returning parsered subcommand\");
-                               Ok(Self::{subcommand_name}{{ {self_new_call}  }})
+                               ::core::result::Result::Ok(Self::{subcommand_name}{{ {self_fields}  }})
                                         }}\n");
-            subcommands_names += &format!("\"{identifier}\",");
         }
         let subcommands_names = subcommands_names
-            .strip_suffix(",")
-            .unwrap_or(&subcommands_fn);
+            .strip_suffix(',')
+            .unwrap_or(&subcommands_names);
+
+        let code = format!("impl {RUNTIME}::SubCommand for {idetifier} {{\n
+                                    fn matches(parser: &{RUNTIME}::ParserInfo, name: &str) -> bool {{
+                                       let rename_all = parser.rename_all;
+                                       [{subcommands_names}].contains(&name)
+                                    }}
 
-        let code = format!("impl {idetifier} {{\n
-                                    pub fn parse<T: Display + ?Sized>(parser: &mut ParserInfo, cmd_val: &T) -> Result<Self, Error> {{
-                                            match cmd_val.to_string().as_str() {{
+                                    fn parse(parser: &mut {RUNTIME}::ParserInfo, name: &str) -> ::core::result::Result<Self, {RUNTIME}::Error> {{
+                                            let rename_all = parser.rename_all;
+                                            parser.enter_command(name);
+                                            {RUNTIME}::log_trace(parser.trace, ::core::format_args!(\"enter subcommand `{{name}}`\"));
+                                            match name {{
                                                 {match_body}
-                                                _ => unreachable!(),
+                                                _ => ::core::result::Result::Err({RUNTIME}::Arg::Value(name.into()).unexpected().into()),
                                             }}
                                     }}
 
-                                    pub fn is_this_subcommad<T: Display + ?Sized>(arg: &T) -> bool {{
-                                       [{subcommands_names}].contains(&arg.to_string().as_str())
+                                    fn commands(rename_all: {RUNTIME}::RenameAll) -> ::std::vec::Vec<{RUNTIME}::DisplayCommand> {{
+                                        ::std::vec![{display_commands}]
                                     }}
-                                    {subcommands_fn}
-                        }}");
+                        }}
+
+                        {checks}");
         writeln!(f, "{code}")
     }
 }
 
-/// EXPAND: Procedural macros expands result in the Parser implementation
+/// EXPAND: Procedural macros expands result in the SubCommand implementation
 /// with all the meta information that the parser needs a runtime.
 ///
 /// ```norun
-/// use lexopt_helper::prelude::*;
-///
-/// struct NameOfYourParser {
-///     pub verbose: bool,
+/// enum Command {
+///     Install { name: String },
 /// }
 ///
-/// // This is generated by the `Parser` derive macros
-/// impl NameOfYourParser {
-///     fn new(verbose: bool) -> Self {
-///         Self{ verbose: verbose }
+/// // This is generated by the `SubCommand` derive macros
+/// impl ::lexopt_cli::prelude::SubCommand for Command {
+///     fn matches(parser: &ParserInfo, name: &str) -> bool {
+///         ["install"].contains(&name)
 ///     }
 ///
-///     fn parse() -> Result<Self, Error> {
-///         let mut parser = ParserInfo::new();
-///         let mut verbose: Option<bool> = None;
-///
-///         while let Some(arg) parser.next()? {
-///             match arg.clone() {
-///                 // perform the match of the arguments
-///                 _ => return Err(arg.unexpected()),
+///     fn parse(parser: &mut ParserInfo, name: &str) -> Result<Self, Error> {
+///         parser.enter_command(name);
+///         match name {
+///             cmd if cmd == "install" => {
+///                 // the same loop of the `Parser` derive macros
+///                 Ok(Self::Install { name })
 ///             }
+///             _ => Err(Arg::Value(name.into()).unexpected().into()),
 ///         }
-///         Ok(Self::new(verbose))
+///     }
+///
+///     fn commands(rename_all: RenameAll) -> Vec<DisplayCommand> {
+///         // the metadata used to print the help
 ///     }
 /// }
 /// ````
 pub fn parse(stream: TokenStream) -> TokenStream {
-    let parser = build_parser!(TRACER);
-    let Ok(ast) = parser.parse(&stream) else {
-        unreachable!()
-    };
-    let Ok(implementation) = generate_impl(ast, &TRACER).map_err(|err| err.emit()) else {
-        return "".parse().unwrap();
+    let implementation = match parse_item(&stream).and_then(|ast| generate_impl(ast, &TRACER)) {
+        Ok(implementation) => implementation,
+        Err(err) => return err.to_compile_error(),
     };
-    implementation.to_string().parse().unwrap()
+    let code = implementation.to_string();
+    if expand::is_enabled() {
+        let identifier = implementation.identifier.clone().unwrap().to_string();
+        expand::dump(&identifier, &code);
+    }
+    code.parse().unwrap()
 }
 
 fn generate_impl<T: KParserTracer>(
-    ast: TopLevelNode,
+    ast: ItemNode,
     _: &T,
-) -> Result<SubCommandMacroInfo, KParserError> {
+) -> Result<SubCommandMacroInfo, MacroError> {
     let mut info = SubCommandMacroInfo {
         identifier: None,
         subcommand: Vec::new(),
     };
     match ast {
-        TopLevelNode::Enum(ast) => {
+        ItemNode::Enum(ast) => {
             info.identifier = Some(ast.identifier);
             for value in ast.values {
                 // FIXME: we can reuse the parser code that we use to generate the
                 // parser derive macro?
                 let mut subcommands = MacroInfo {
+                    name: RenamedNames::from_ident(&value.identifier.to_string()),
+                    doc: value.doc(),
                     identifier: value.identifier,
                     fields: Vec::new(),
                     subcommands: Vec::new(),
+                    flattened: Vec::new(),
+                    wrapped: None,
                 };
                 match value.kind {
-                    // FIXME: the value should be a vector of struct
-                    // FIXME: we are missing the inner attributes
-                    EnumValueKind::Named(value) => {
-                        for (identifier, ty) in value.into_iter() {
-                            subcommands.fields.push(ArgsInfo {
-                                ty,
-                                long_name: identifier,
-                                short_name: None,
-                            });
+                    VariantKind::Named(fields) => {
+                        for field in fields.iter() {
+                            if field.has_attr("subcommand") {
+                                let info = SubCommandInfo::from_field(field);
+                                subcommands.subcommands.push(info);
+                                continue;
+                            }
+                            if field.has_attr("flatten") {
+                                let info = FlattenInfo::from_field(field);
+                                subcommands.flattened.push(info);
+                                continue;
+                            }
+                            subcommands.fields.push(ArgsInfo::from_field(field)?);
                         }
+                        sort_positionals(&mut subcommands.fields)?;
+                        check_short_names(&subcommands.fields)?;
                     }
-                    EnumValueKind::Anonymus(_) => {
-                        // FIXME: generate warning, that this kind of function
+                    VariantKind::Unnamed(mut types) => {
+                        if types.len() != 1 {
+                            return Err(build_error!(
+                                subcommands.identifier,
+                                "a tuple variant must wrap a single struct that derives Parser"
+                            ));
+                        }
+                        subcommands.wrapped = types.pop();
                     }
-                    EnumValueKind::Simple => {}
+                    VariantKind::Unit => {}
                 }
-                // TODO: Feel the subcommand and fields
                 info.subcommand.push(subcommands);
             }
         }
-        _ => unimplemented!("only enum are supported as subcommands"),
+        ItemNode::Struct(ast) => {
+            return Err(build_error!(
+                ast.identifier,
+                "SubCommand can be derived only on enums, use Parser for structs"
+            ))
+        }
     }
 
     Ok(info)
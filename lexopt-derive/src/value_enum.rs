@@ -0,0 +1,188 @@
+//! Value Enum derive macro implementation
+use std::fmt;
+
+use kproc_parser::proc_macro::{TokenStream, TokenTree};
+
+use crate::ast::{parse_item, ItemNode, VariantKind};
+use crate::error::MacroError;
+use crate::expand;
+use crate::rename::RenamedNames;
+use crate::RUNTIME;
+
+struct ValueEnumMacroInfo {
+    pub identifier: TokenTree,
+    /// The values are matched without looking at the case, set
+    /// with `#[value(ignore_case)]` on the enum.
+    pub ignore_case: bool,
+    pub values: Vec<ValueInfo>,
+}
+
+struct ValueInfo {
+    pub identifier: TokenTree,
+    /// The name on the command line, the kebab-case identifier
+    /// or the one set with `#[value(name = "...")]`.
+    pub name: String,
+    /// The other names set with `#[value(alias = "...")]`.
+    pub aliases: Vec<String>,
+}
+
+impl ValueInfo {
+    /// All the names that are accepted on the command line.
+    fn names(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.name).chain(self.aliases.iter())
+    }
+}
+
+impl fmt::Display for ValueEnumMacroInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let identifier = &self.identifier;
+        let mut match_body = String::new();
+        let mut possible_values = String::new();
+        for value in self.values.iter() {
+            let patterns = value
+                .names()
+                .map(|name| match self.ignore_case {
+                    true => format!("{:?}", name.to_lowercase()),
+                    false => format!("{name:?}"),
+                })
+                .collect::<Vec<_>>()
+                .join(" | ");
+            let variant = &value.identifier;
+            match_body += &format!("{patterns} => ::core::result::Result::Ok(Self::{variant}),\n");
+            possible_values += &format!("{:?},", value.name);
+        }
+        let value = match self.ignore_case {
+            true => "value.to_lowercase()",
+            false => "value",
+        };
+        let code = format!(
+            "impl ::core::str::FromStr for {identifier} {{
+                type Err = ::std::string::String;
+
+                fn from_str(value: &str) -> ::core::result::Result<Self, Self::Err> {{
+                    let value = {value};
+                    match &*value {{
+                        {match_body}
+                        _ => ::core::result::Result::Err(::std::format!(
+                            \"expected one of {{}}\",
+                            <Self as {RUNTIME}::ValueEnum>::POSSIBLE_VALUES.join(\", \")
+                        )),
+                    }}
+                }}
+            }}
+
+            impl {RUNTIME}::ValueEnum for {identifier} {{
+                const POSSIBLE_VALUES: &'static [&'static str] = &[{possible_values}];
+            }}"
+        );
+        writeln!(f, "{code}")
+    }
+}
+
+/// EXPAND: Procedural macros expands result in the `FromStr` and
+/// `ValueEnum` implementations of an enum with unit variants.
+///
+/// ```norun
+/// #[value(ignore_case)]
+/// enum Format {
+///     Json,
+///     #[value(alias = "yml")]
+///     Yaml,
+/// }
+///
+/// // This is generated by the `ValueEnum` derive macros
+/// impl FromStr for Format {
+///     type Err = String;
+///
+///     fn from_str(value: &str) -> Result<Self, Self::Err> {
+///         let value = value.to_lowercase();
+///         match &*value {
+///             "json" => Ok(Self::Json),
+///             "yaml" | "yml" => Ok(Self::Yaml),
+///             _ => Err(format!("expected one of {}", Self::POSSIBLE_VALUES.join(", "))),
+///         }
+///     }
+/// }
+///
+/// impl ValueEnum for Format {
+///     const POSSIBLE_VALUES: &'static [&'static str] = &["json", "yaml"];
+/// }
+/// ````
+pub fn parse(stream: TokenStream) -> TokenStream {
+    let implementation = match parse_item(&stream).and_then(generate_impl) {
+        Ok(implementation) => implementation,
+        Err(err) => return err.to_compile_error(),
+    };
+    let code = implementation.to_string();
+    if expand::is_enabled() {
+        expand::dump(&implementation.identifier.to_string(), &code);
+    }
+    code.parse().unwrap()
+}
+
+fn generate_impl(ast: ItemNode) -> Result<ValueEnumMacroInfo, MacroError> {
+    let ast = match ast {
+        ItemNode::Enum(ast) => ast,
+        ItemNode::Struct(ast) => {
+            return Err(build_error!(
+                ast.identifier,
+                "ValueEnum can be derived only on enums with unit variants"
+            ))
+        }
+    };
+    let mut info = ValueEnumMacroInfo {
+        identifier: ast.identifier.clone(),
+        ignore_case: false,
+        values: Vec::new(),
+    };
+    for arg in ast.attr_args("value")? {
+        match arg.key.to_string().as_str() {
+            "ignore_case" => info.ignore_case = true,
+            _ => return Err(build_error!(arg.key, "unknown `value` attribute")),
+        }
+    }
+    for variant in ast.values {
+        if !matches!(variant.kind, VariantKind::Unit) {
+            return Err(build_error!(
+                variant.identifier,
+                "ValueEnum supports only unit variants"
+            ));
+        }
+        let identifier = variant.identifier.to_string();
+        let mut value = ValueInfo {
+            name: RenamedNames::from_ident(&identifier)
+                .default_name()
+                .to_owned(),
+            aliases: Vec::new(),
+            identifier: variant.identifier.clone(),
+        };
+        for arg in variant.attr_args("value")? {
+            match arg.key.to_string().as_str() {
+                "name" => value.name = arg.str_value()?,
+                "alias" => value.aliases.push(arg.str_value()?),
+                _ => return Err(build_error!(arg.key, "unknown `value` attribute")),
+            }
+        }
+        for name in value.names() {
+            let same_name = |other: &String| match info.ignore_case {
+                true => other.to_lowercase() == name.to_lowercase(),
+                false => other == name,
+            };
+            if let Some(other) = info
+                .values
+                .iter()
+                .find(|other| other.names().any(same_name))
+            {
+                return Err(build_error!(
+                    variant.identifier,
+                    &format!(
+                        "the value `{name}` is already used by `{}`",
+                        other.identifier
+                    )
+                ));
+            }
+        }
+        info.values.push(value);
+    }
+    Ok(info)
+}
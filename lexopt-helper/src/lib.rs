@@ -1,49 +1,729 @@
 pub mod prelude {
     pub use lexopt::prelude::*;
     pub use lexopt::Arg;
-    pub use lexopt::Error;
     pub use lexopt::Parser as LexParser;
 
     use std::collections::HashMap;
     use std::ffi::OsString;
+    use std::fmt;
+
+    /// The error returned by the generated parsers.
+    #[derive(Debug)]
+    pub enum Error {
+        /// An error returned by lexopt while parsing the arguments.
+        Lexopt(lexopt::Error),
+        /// A required argument is missing, e.g. `--name`.
+        MissingArgument(String),
+        /// A required subcommand is missing, contains the
+        /// name of the field that stores the subcommand.
+        MissingSubCommand(String),
+        /// `-h/--help` was found, contains the rendered help.
+        DisplayHelp(String),
+        /// `-V/--version` was found, contains the rendered version.
+        DisplayVersion(String),
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Error::Lexopt(err) => write!(f, "{err}"),
+                Error::MissingArgument(name) => write!(f, "missing required argument '{name}'"),
+                Error::MissingSubCommand(name) => {
+                    write!(f, "missing required subcommand '<{name}>'")
+                }
+                Error::DisplayHelp(message) | Error::DisplayVersion(message) => {
+                    write!(f, "{message}")
+                }
+            }
+        }
+    }
+
+    impl std::error::Error for Error {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                Error::Lexopt(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+
+    impl From<lexopt::Error> for Error {
+        fn from(err: lexopt::Error) -> Self {
+            Error::Lexopt(err)
+        }
+    }
+
+    /// The style used to build the names of the arguments and of the
+    /// subcommands from the rust identifiers, chosen with
+    /// `#[cli(rename_all = "...")]`.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum RenameAll {
+        /// `dry_run` became `dry-run` and `InstallPkg` became `install-pkg`
+        #[default]
+        KebabCase,
+        /// `dry_run` stay `dry_run` and `InstallPkg` became `install_pkg`
+        SnakeCase,
+        /// the names are the same of the rust identifiers
+        Verbatim,
+    }
+
+    impl RenameAll {
+        /// Select the name for this style between the names generated by
+        /// the derive macros, in the same order of the enum variants.
+        pub fn select<'a>(&self, names: [&'a str; 3]) -> &'a str {
+            names[*self as usize]
+        }
+    }
+
+    /// A command line parser, implemented by `#[derive(Parser)]`.
+    pub trait Parser: Sized {
+        /// The style of the names, chosen with `#[cli(rename_all = "...")]`.
+        const RENAME_ALL: RenameAll = RenameAll::KebabCase;
+
+        /// The prefix of the environment variables of the arguments,
+        /// chosen with `#[cli(env_prefix = "...")]`.
+        const ENV_PREFIX: &'static str = "";
+
+        /// The command with the names in the `rename_all` style, used
+        /// also when the parser is wrapped by a subcommand.
+        fn display_command(rename_all: RenameAll) -> DisplayCommand;
+
+        /// Parse the arguments of the command, the command is already
+        /// registered inside the parser, e.g. by a parent command.
+        fn parse_args(parser: &mut ParserInfo) -> Result<Self, Error>;
+
+        /// The command used to print the help, with the usage and
+        /// the environment variables of all the subcommands.
+        fn command() -> DisplayCommand {
+            let mut command = Self::display_command(Self::RENAME_ALL);
+            command.fill_usage("");
+            command.fill_env_prefix(Self::ENV_PREFIX);
+            command
+        }
+
+        /// Parse the arguments of the process.
+        fn try_parse() -> Result<Self, Error> {
+            Self::try_parse_with(ParserInfo::new())
+        }
+
+        /// Parse the given arguments, without the name of the binary.
+        fn try_parse_from<I>(args: I) -> Result<Self, Error>
+        where
+            I: IntoIterator,
+            I::Item: Into<OsString>,
+        {
+            Self::try_parse_with(ParserInfo::from_args(args))
+        }
+
+        /// Parse the arguments that are inside the parser, e.g. a
+        /// parser built with `ParserInfo::from_args`.
+        fn try_parse_with(mut parser: ParserInfo) -> Result<Self, Error> {
+            parser.rename_all = Self::RENAME_ALL;
+            parser.env_prefix = Self::ENV_PREFIX.to_owned();
+            parser.register_command(Self::command());
+            Self::parse_args(&mut parser)
+        }
+
+        /// Parse the arguments of the process, on error the message
+        /// is printed on stderr and the process exits. The help and
+        /// the version are printed on stdout with a success exit code.
+        fn parse() -> Self {
+            exit_on_error(Self::try_parse())
+        }
+
+        /// Parse the given arguments, without the name of the binary,
+        /// on error the message is printed on stderr and the process exits.
+        fn parse_from<I>(args: I) -> Self
+        where
+            I: IntoIterator,
+            I::Item: Into<OsString>,
+        {
+            exit_on_error(Self::try_parse_from(args))
+        }
+    }
+
+    fn exit_on_error<T>(result: Result<T, Error>) -> T {
+        match result {
+            Ok(value) => value,
+            Err(Error::DisplayHelp(message) | Error::DisplayVersion(message)) => {
+                print!("{message}");
+                std::process::exit(0);
+            }
+            Err(err) => {
+                eprintln!("error: {err}");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    /// The items used to merge a parser inside another one with
+    /// `#[flatten]`, implemented by `#[derive(Parser)]` together
+    /// with `Parser`.
+    #[doc(hidden)]
+    pub trait FlattenArgs: Parser {
+        /// The variables that store the arguments while they are parsed.
+        type Flags;
+
+        /// The names of the options, used to check at compile time
+        /// that the flattened parsers do not collide.
+        const ARG_NAMES: ArgNames;
+
+        /// The variables before that the arguments are parsed.
+        fn flags() -> Self::Flags;
+
+        /// Store the argument inside the flags when it is one of the
+        /// options, the returned bool is false when it does not match.
+        fn match_flag(
+            flags: Self::Flags,
+            parser: &mut ParserInfo,
+            arg: &Arg<'_>,
+        ) -> Result<(Self::Flags, bool), Error>;
+
+        /// Build the parser from the flags, when the parent parser
+        /// has consumed all the arguments.
+        fn from_flags(flags: Self::Flags, parser: &mut ParserInfo) -> Result<Self, Error>;
+
+        /// The arguments of the command, without `-h/--help`
+        /// and `-V/--version`.
+        fn display_args(rename_all: RenameAll) -> Vec<DisplayArg>;
+
+        /// Like `match_flag`, but the flags are updated in place.
+        fn parse_flag(
+            flags: &mut Self::Flags,
+            parser: &mut ParserInfo,
+            arg: &Arg<'_>,
+        ) -> Result<bool, Error> {
+            let old_flags = std::mem::replace(flags, Self::flags());
+            let (new_flags, matched) = Self::match_flag(old_flags, parser, arg)?;
+            *flags = new_flags;
+            Ok(matched)
+        }
+    }
+
+    /// The subcommands of a parser, implemented by `#[derive(SubCommand)]`.
+    pub trait SubCommand: Sized {
+        /// Return true if the name is the one of a subcommand.
+        fn matches(parser: &ParserInfo, name: &str) -> bool;
+
+        /// Parse the subcommand with the given name.
+        fn parse(parser: &mut ParserInfo, name: &str) -> Result<Self, Error>;
+
+        /// The commands with the names in the `rename_all` style.
+        fn commands(rename_all: RenameAll) -> Vec<DisplayCommand>;
+    }
+
+    /// An enum with unit variants used as the value of an argument,
+    /// implemented by `#[derive(ValueEnum)]` together with `FromStr`.
+    pub trait ValueEnum: Sized {
+        /// The names of the values on the command line, without the aliases.
+        const POSSIBLE_VALUES: &'static [&'static str];
+    }
+
+    /// The names of the options of a parser and of the parsers flattened
+    /// inside it, the long names are the ones of the kebab-case style.
+    #[derive(Clone, Copy, Debug)]
+    pub struct ArgNames {
+        pub long: &'static [&'static str],
+        pub short: &'static [char],
+        /// The parser reads positional arguments or subcommands,
+        /// that can not be merged inside another parser.
+        pub values: bool,
+        pub flattened: &'static [ArgNames],
+    }
+
+    impl ArgNames {
+        /// Return true if two options of the parser and of the
+        /// flattened parsers have the same name, or if the flattened
+        /// parsers leave no name to `-h/--help` or to `-V/--version`.
+        pub const fn collides(&self) -> bool {
+            if self.hides_builtin("help", 'h') || self.hides_builtin("version", 'V') {
+                return true;
+            }
+            let mut idx = 0;
+            while idx < self.flattened.len() {
+                let flattened = &self.flattened[idx];
+                if flattened.contains_any(self.long, self.short) {
+                    return true;
+                }
+                let mut other = idx + 1;
+                while other < self.flattened.len() {
+                    if flattened.intersects(&self.flattened[other]) {
+                        return true;
+                    }
+                    other += 1;
+                }
+                idx += 1;
+            }
+            false
+        }
+
+        /// Return true if one of the flattened parsers reads values.
+        pub const fn flattens_values(&self) -> bool {
+            let mut idx = 0;
+            while idx < self.flattened.len() {
+                if self.flattened[idx].values {
+                    return true;
+                }
+                idx += 1;
+            }
+            false
+        }
+
+        /// Return true if the long name is an option of this parser
+        /// or of the flattened ones.
+        pub const fn has_long(&self, name: &str) -> bool {
+            self.contains_any(&[name], &[])
+        }
+
+        /// Return true if the short name is an option of this parser
+        /// or of the flattened ones.
+        pub const fn has_short(&self, name: char) -> bool {
+            self.contains_any(&[], &[name])
+        }
+
+        /// Return true if both names of a builtin option are taken and
+        /// the flattened parsers take at least one of them. The options
+        /// of the parser can replace the builtin one on their own.
+        const fn hides_builtin(&self, long: &str, short: char) -> bool {
+            if !self.has_long(long) || !self.has_short(short) {
+                return false;
+            }
+            let mut idx = 0;
+            while idx < self.flattened.len() {
+                if self.flattened[idx].contains_any(&[long], &[short]) {
+                    return true;
+                }
+                idx += 1;
+            }
+            false
+        }
+
+        /// Return true if an option of `other` is also an option of this parser.
+        const fn intersects(&self, other: &ArgNames) -> bool {
+            if self.contains_any(other.long, other.short) {
+                return true;
+            }
+            let mut idx = 0;
+            while idx < other.flattened.len() {
+                if self.intersects(&other.flattened[idx]) {
+                    return true;
+                }
+                idx += 1;
+            }
+            false
+        }
+
+        /// Return true if one of the names is an option of this parser.
+        const fn contains_any(&self, long: &[&str], short: &[char]) -> bool {
+            let mut idx = 0;
+            while idx < long.len() {
+                let mut own = 0;
+                while own < self.long.len() {
+                    if str_eq(long[idx], self.long[own]) {
+                        return true;
+                    }
+                    own += 1;
+                }
+                idx += 1;
+            }
+            let mut idx = 0;
+            while idx < short.len() {
+                let mut own = 0;
+                while own < self.short.len() {
+                    if short[idx] == self.short[own] {
+                        return true;
+                    }
+                    own += 1;
+                }
+                idx += 1;
+            }
+            let mut idx = 0;
+            while idx < self.flattened.len() {
+                if self.flattened[idx].contains_any(long, short) {
+                    return true;
+                }
+                idx += 1;
+            }
+            false
+        }
+    }
+
+    /// Compare two strings inside a const function.
+    const fn str_eq(left: &str, right: &str) -> bool {
+        let (left, right) = (left.as_bytes(), right.as_bytes());
+        if left.len() != right.len() {
+            return false;
+        }
+        let mut idx = 0;
+        while idx < left.len() {
+            if left[idx] != right[idx] {
+                return false;
+            }
+            idx += 1;
+        }
+        true
+    }
 
     pub struct ParserInfo {
         pub command_map: HashMap<String, DisplayCommand>,
+        pub rename_all: RenameAll,
+        /// The prefix of the environment variables of the arguments.
+        pub env_prefix: String,
+        /// The key inside the `command_map` of the command
+        /// that is parsed at the moment.
+        pub current_command: String,
+        /// Report on stderr each token and the field where it is
+        /// stored, enabled with the `LEXOPT_TRACE` environment variable.
+        pub trace: bool,
         cmd_parser: LexParser,
     }
 
     impl ParserInfo {
+        /// Build the parser of the arguments of the process.
         pub fn new() -> Self {
+            Self::with_parser(LexParser::from_env())
+        }
+
+        /// Build the parser of the given arguments, that must not
+        /// contain the name of the binary, e.g. `["install", "-n", "foo"]`.
+        pub fn from_args<I>(args: I) -> Self
+        where
+            I: IntoIterator,
+            I::Item: Into<OsString>,
+        {
+            Self::with_parser(LexParser::from_args(args))
+        }
+
+        fn with_parser(cmd_parser: LexParser) -> Self {
             ParserInfo {
                 command_map: HashMap::new(),
-                cmd_parser: LexParser::from_env(),
+                rename_all: RenameAll::default(),
+                env_prefix: String::new(),
+                current_command: "@".to_owned(),
+                trace: std::env::var_os("LEXOPT_TRACE").is_some_and(|value| value != "0"),
+                cmd_parser,
             }
         }
 
+        #[allow(clippy::should_implement_trait)]
         pub fn next(&mut self) -> Result<Option<Arg<'_>>, Error> {
-            self.cmd_parser.next()
+            Ok(self.cmd_parser.next()?)
         }
 
         pub fn value(&mut self) -> Result<OsString, Error> {
-            self.cmd_parser.value()
+            Ok(self.cmd_parser.value()?)
+        }
+
+        /// Store the command and all its subcommands inside the `command_map`.
+        ///
+        /// The top level command is stored with the `@` key, while the
+        /// subcommands are stored with their path, e.g. `remote add`.
+        /// The command is the finished one returned by `Parser::command`.
+        pub fn register_command(&mut self, command: DisplayCommand) {
+            self.register_subcommands("", &command);
+            self.command_map.insert("@".to_owned(), command);
+        }
+
+        /// Move the parser inside the subcommand with the given name,
+        /// so the help printed is the one of the subcommand.
+        pub fn enter_command(&mut self, name: &str) {
+            self.current_command = match self.current_command.as_str() {
+                "@" => name.to_owned(),
+                parent => format!("{parent} {name}"),
+            };
+        }
+
+        /// Render the help of the command that is parsed at the moment,
+        /// the long help is rendered for `--help`.
+        pub fn render_help(&self, long: bool) -> String {
+            self.command_map
+                .get(&self.current_command)
+                .map(|command| command.render_help(long))
+                .unwrap_or_default()
+        }
+
+        /// Render the name and the version of the top level command.
+        pub fn render_version(&self) -> String {
+            self.command_map
+                .get("@")
+                .map(|command| format!("{} {}\n", command.name, command.version))
+                .unwrap_or_default()
+        }
+
+        fn register_subcommands(&mut self, prefix: &str, command: &DisplayCommand) {
+            for subcommand in command.subcommands.iter() {
+                let key = match prefix.is_empty() {
+                    true => subcommand.name.clone(),
+                    false => format!("{prefix} {}", subcommand.name),
+                };
+                self.register_subcommands(&key, subcommand);
+                self.command_map.insert(key, subcommand.clone());
+            }
+        }
+
+        /// Return the value attached to the last option, if any.
+        ///
+        /// This never consumes the next argument, so it is used
+        /// by switches to accept `--verbose=false`.
+        pub fn optional_value(&mut self) -> Option<OsString> {
+            self.cmd_parser.optional_value()
+        }
+    }
+
+    impl Default for ParserInfo {
+        fn default() -> Self {
+            Self::new()
         }
     }
 
-    #[derive(Clone)]
+    #[derive(Clone, Debug)]
     pub struct DisplayCommand {
         pub name: String,
         pub subcommands: Vec<DisplayCommand>,
+        /// The subcommand can be missing, so the usage
+        /// shows `[COMMAND]` instead of `<COMMAND>`.
+        pub optional_subcommand: bool,
         pub args: Vec<DisplayArg>,
         pub usage: String,
+        /// The first paragraph of the doc comment, or the `about`.
         pub description: String,
+        /// The other paragraphs of the doc comment.
+        pub long_description: String,
+        /// The version of the command, empty for the subcommands.
+        pub version: String,
+        /// The author of the command, empty for the subcommands.
+        pub author: String,
     }
 
-    #[derive(Clone)]
+    impl DisplayCommand {
+        /// Render the help message of the command, with the usage,
+        /// the subcommands and the arguments aligned in columns.
+        ///
+        /// The long help contains also the long descriptions.
+        pub fn render_help(&self, long: bool) -> String {
+            let mut help = String::new();
+            if !self.version.is_empty() {
+                help += &format!("{} {}\n", self.name, self.version);
+            }
+            if !self.author.is_empty() {
+                help += &format!("{}\n", self.author);
+            }
+            if !help.is_empty() && !self.description.is_empty() {
+                help += "\n";
+            }
+            let description = match long {
+                true => join_descriptions(&self.description, &self.long_description),
+                false => self.description.clone(),
+            };
+            if !description.is_empty() {
+                help += &format!("{description}\n\n");
+            }
+            help += &format!("Usage: {}\n", self.usage);
+
+            let commands = self
+                .subcommands
+                .iter()
+                .map(|command| (command.name.clone(), command.description.clone()))
+                .collect();
+            help += &render_section("Commands", commands);
+
+            let (positionals, options): (Vec<_>, Vec<_>) =
+                self.args.iter().partition(|arg| arg.positional);
+            let positionals = positionals
+                .into_iter()
+                .map(|arg| (arg.value_name(), arg.help(long)))
+                .collect();
+            help += &render_section("Arguments", positionals);
+            let options = options
+                .into_iter()
+                .map(|arg| (arg.option_name(), arg.help(long)))
+                .collect();
+            help += &render_section("Options", options);
+            help
+        }
+
+        /// Build the usage of the command and of all its subcommands,
+        /// e.g. `es install [OPTIONS] <name>`.
+        fn fill_usage(&mut self, parent: &str) {
+            let path = match parent.is_empty() {
+                true => self.name.clone(),
+                false => format!("{parent} {}", self.name),
+            };
+            let mut usage = path.clone();
+            if self.args.iter().any(|arg| !arg.positional) {
+                usage += " [OPTIONS]";
+            }
+            for arg in self.args.iter().filter(|arg| arg.positional) {
+                let name = &arg.long_name;
+                usage += &match (arg.optional, arg.multiple) {
+                    (_, true) => format!(" [{name}]..."),
+                    (true, false) => format!(" [{name}]"),
+                    (false, false) => format!(" <{name}>"),
+                };
+            }
+            if !self.subcommands.is_empty() {
+                usage += match self.optional_subcommand {
+                    true => " [COMMAND]",
+                    false => " <COMMAND>",
+                };
+            }
+            self.usage = usage;
+            for subcommand in self.subcommands.iter_mut() {
+                subcommand.fill_usage(&path);
+            }
+        }
+
+        /// Add the prefix to the environment variables named after the
+        /// fields, of the command and of all its subcommands.
+        fn fill_env_prefix(&mut self, prefix: &str) {
+            for arg in self.args.iter_mut().filter(|arg| arg.env_prefixed) {
+                arg.env = format!("{prefix}{}", arg.env);
+            }
+            for subcommand in self.subcommands.iter_mut() {
+                subcommand.fill_env_prefix(prefix);
+            }
+        }
+    }
+
+    #[derive(Clone, Debug)]
     pub struct DisplayArg {
         pub optional: bool,
         pub long_name: String,
+        /// The short name, empty when the argument does not have one.
         pub short_name: String,
+        /// The first paragraph of the doc comment.
         pub description: String,
+        /// The other paragraphs of the doc comment.
+        pub long_description: String,
+        /// The argument is a value without a name.
+        pub positional: bool,
+        /// The argument needs a value, false for the switches.
+        pub takes_value: bool,
+        /// The argument can be repeated.
+        pub multiple: bool,
+        /// The environment variable read when the argument is
+        /// missing, empty when there is none.
+        pub env: String,
+        /// The environment variable is named after the field, so
+        /// it gets the prefix of the command.
+        pub env_prefixed: bool,
+        /// The value used when the argument is missing, empty
+        /// when there is none.
+        pub default: String,
+        /// The values accepted by the argument, set with `#[arg(value_enum)]`.
+        pub possible_values: Vec<String>,
+    }
+
+    impl DisplayArg {
+        fn help(&self, long: bool) -> String {
+            let mut help = match long {
+                true => join_descriptions(&self.description, &self.long_description),
+                false => self.description.clone(),
+            };
+            let possible_values = self.possible_values.join(", ");
+            let notes = [
+                ("default", &self.default),
+                ("possible values", &possible_values),
+                ("env", &self.env),
+            ];
+            for (name, value) in notes.into_iter().filter(|(_, value)| !value.is_empty()) {
+                if !help.is_empty() {
+                    help += " ";
+                }
+                help += &format!("[{name}: {value}]");
+            }
+            help
+        }
+
+        /// The name of a positional argument, e.g. `<name>` or `[name]...`.
+        fn value_name(&self) -> String {
+            let name = &self.long_name;
+            match (self.optional, self.multiple) {
+                (_, true) => format!("[{name}]..."),
+                (true, false) => format!("[{name}]"),
+                (false, false) => format!("<{name}>"),
+            }
+        }
+
+        /// The name of an option, e.g. `-n, --name <name>`.
+        fn option_name(&self) -> String {
+            let mut name = match self.short_name.is_empty() {
+                true => "    ".to_owned(),
+                false => format!("-{}, ", self.short_name),
+            };
+            if self.long_name.is_empty() {
+                return name.trim_end_matches([',', ' ']).to_owned();
+            }
+            name += &format!("--{}", self.long_name);
+            if self.takes_value {
+                name += &format!(" <{}>", self.long_name);
+            }
+            if self.multiple {
+                name += "...";
+            }
+            name
+        }
+    }
+
+    fn join_descriptions(description: &str, long_description: &str) -> String {
+        match long_description.is_empty() {
+            true => description.to_owned(),
+            false => format!("{description}\n\n{long_description}"),
+        }
+    }
+
+    /// Render a section of the help, where each row has a name
+    /// and a description aligned in two columns.
+    ///
+    /// The lines after the first one of a description are
+    /// aligned with the description column.
+    fn render_section(title: &str, rows: Vec<(String, String)>) -> String {
+        if rows.is_empty() {
+            return String::new();
+        }
+        let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        let indent = " ".repeat(width + 4);
+        let mut section = format!("\n{title}:\n");
+        for (name, description) in rows {
+            let description = description.replace('\n', &format!("\n{indent}"));
+            let row = format!("  {name:width$}  {description}");
+            for line in row.lines() {
+                section += line.trim_end();
+                section += "\n";
+            }
+        }
+        section
+    }
+
+    /// Print a message of the runtime trace on stderr, when it is enabled.
+    pub fn log_trace(enabled: bool, msg: fmt::Arguments<'_>) {
+        if enabled {
+            eprintln!("lexopt: {msg}");
+        }
+    }
+
+    /// Parse the value of a switch read from an environment variable,
+    /// that can be `1` and `0` besides `true` and `false`.
+    pub fn parse_env_switch(value: &OsString) -> Result<bool, Error> {
+        match value.to_str() {
+            Some("1") => Ok(true),
+            Some("0") => Ok(false),
+            _ => Ok(value.parse()?),
+        }
+    }
+
+    /// Copy the argument so it does not borrow the parser anymore, the
+    /// long name is copied inside `long`. Used to pass the argument to
+    /// the flattened parsers, that can read the next value.
+    pub fn detach_arg<'a>(arg: &Arg<'_>, long: &'a mut String) -> Arg<'a> {
+        match arg {
+            Short(short) => Short(*short),
+            Long(name) => {
+                long.push_str(name);
+                Long(long)
+            }
+            Value(value) => Value(value.clone()),
+        }
     }
 
     pub fn arg_to_string<'a>(arg: Arg<'a>) -> String {
@@ -54,3 +734,207 @@ pub mod prelude {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use super::prelude::*;
+
+    fn arg(long_name: &str, short_name: &str, description: &str) -> DisplayArg {
+        DisplayArg {
+            optional: true,
+            long_name: long_name.to_owned(),
+            short_name: short_name.to_owned(),
+            description: description.to_owned(),
+            long_description: String::new(),
+            positional: false,
+            takes_value: false,
+            multiple: false,
+            env: String::new(),
+            env_prefixed: false,
+            default: String::new(),
+            possible_values: vec![],
+        }
+    }
+
+    fn command(name: &str, args: Vec<DisplayArg>) -> DisplayCommand {
+        DisplayCommand {
+            name: name.to_owned(),
+            subcommands: vec![],
+            optional_subcommand: false,
+            args,
+            usage: String::new(),
+            description: String::new(),
+            long_description: String::new(),
+            version: String::new(),
+            author: String::new(),
+        }
+    }
+
+    #[test]
+    fn render_help_aligns_the_columns() {
+        let mut name = arg("name", "n", "The name\nof the package");
+        name.takes_value = true;
+        let mut target = arg("target", "", "Where to install");
+        target.positional = true;
+        target.default = ".".to_owned();
+        name.possible_values = vec!["foo".to_owned(), "bar".to_owned()];
+        let mut verbose = arg("verbose", "v", "");
+        verbose.env = "ES_VERBOSE".to_owned();
+        let mut install = command("install", vec![name, verbose, target]);
+        install.usage = "es install [OPTIONS] [target]".to_owned();
+        install.description = "Install a package".to_owned();
+        install.long_description = "The package is downloaded first.".to_owned();
+
+        let expected = "Install a package
+
+Usage: es install [OPTIONS] [target]
+
+Arguments:
+  [target]  Where to install [default: .]
+
+Options:
+  -n, --name <name>  The name
+                     of the package [possible values: foo, bar]
+  -v, --verbose      [env: ES_VERBOSE]
+";
+        assert_eq!(install.render_help(false), expected);
+        let long_help = install.render_help(true);
+        assert!(long_help.starts_with("Install a package\n\nThe package is downloaded first.\n\n"));
+    }
+
+    #[test]
+    fn parse_env_switch_accepts_the_numbers() {
+        let parse = |value: &str| parse_env_switch(&OsString::from(value)).ok();
+        assert_eq!(parse("1"), Some(true));
+        assert_eq!(parse("0"), Some(false));
+        assert_eq!(parse("true"), Some(true));
+        assert_eq!(parse("false"), Some(false));
+        assert_eq!(parse("yes"), None);
+    }
+
+    #[test]
+    fn render_help_lists_the_subcommands() {
+        let mut remote = command("remote", vec![]);
+        remote.description = "Manage the remotes".to_owned();
+        let mut git = command("git", vec![arg("", "q", "Quiet")]);
+        git.subcommands = vec![remote, command("log", vec![])];
+        git.usage = "git [OPTIONS] <COMMAND>".to_owned();
+        git.version = "1.0.0".to_owned();
+
+        let expected = "git 1.0.0
+Usage: git [OPTIONS] <COMMAND>
+
+Commands:
+  remote  Manage the remotes
+  log
+
+Options:
+  -q  Quiet
+";
+        assert_eq!(git.render_help(false), expected);
+    }
+
+    const COMMON: ArgNames = ArgNames {
+        long: &["verbose", "format"],
+        short: &['v'],
+        values: false,
+        flattened: &[],
+    };
+
+    #[test]
+    fn arg_names_without_collisions() {
+        let names = ArgNames {
+            long: &["name"],
+            short: &['n'],
+            values: true,
+            flattened: &[COMMON],
+        };
+        assert!(!names.collides());
+        assert!(!names.flattens_values());
+    }
+
+    #[test]
+    fn arg_names_collide_with_the_flattened_names() {
+        let long = ArgNames {
+            long: &["format"],
+            short: &[],
+            values: false,
+            flattened: &[COMMON],
+        };
+        assert!(long.collides());
+        let short = ArgNames {
+            long: &["value"],
+            short: &['v'],
+            values: false,
+            flattened: &[COMMON],
+        };
+        assert!(short.collides());
+    }
+
+    #[test]
+    fn arg_names_collide_between_the_flattened_parsers() {
+        const NESTED: ArgNames = ArgNames {
+            long: &[],
+            short: &[],
+            values: false,
+            flattened: &[COMMON],
+        };
+        const OTHER: ArgNames = ArgNames {
+            long: &["verbose"],
+            short: &[],
+            values: false,
+            flattened: &[],
+        };
+        let names = ArgNames {
+            long: &[],
+            short: &[],
+            values: false,
+            flattened: &[NESTED, OTHER],
+        };
+        assert!(names.collides());
+        let names = ArgNames {
+            long: &["format"],
+            short: &[],
+            values: false,
+            flattened: &[NESTED],
+        };
+        assert!(names.collides());
+    }
+
+    #[test]
+    fn arg_names_collide_with_the_builtin_names() {
+        const CONN: ArgNames = ArgNames {
+            long: &["host"],
+            short: &['h'],
+            values: false,
+            flattened: &[],
+        };
+        // `--help` is still free
+        let names = ArgNames {
+            long: &[],
+            short: &[],
+            values: false,
+            flattened: &[CONN],
+        };
+        assert!(!names.collides());
+        assert!(names.has_short('h'));
+        assert!(!names.has_long("help"));
+        let names = ArgNames {
+            long: &["help"],
+            short: &[],
+            values: false,
+            flattened: &[CONN],
+        };
+        assert!(names.collides());
+        // the options of the parser can replace the builtin ones
+        let names = ArgNames {
+            long: &["version"],
+            short: &['V'],
+            values: false,
+            flattened: &[CONN],
+        };
+        assert!(!names.collides());
+    }
+}